@@ -0,0 +1,394 @@
+//! Module containing the planning of balancing liquidity provisions, which is
+//! shared between the execute and simulation entry points
+
+use cosmwasm_std::{Decimal, Deps, Env, Uint128};
+use cw_asset::{Asset, AssetInfo, AssetList};
+use cw_dex::traits::Pool;
+
+use crate::error::ContractError;
+use crate::strategy::BalancingStrategy;
+
+/// The swap and liquidity provision needed to provide liquidity to a pool with
+/// a possibly imbalanced set of assets
+pub struct BalancingProvidePlan {
+    /// The swap done to balance the assets, as `(offer_asset, return_asset)`.
+    /// `None` if no swap is needed, which is the case for empty pools and for
+    /// pools accepting liquidity in any ratio where swapping would not give
+    /// more LP tokens.
+    pub swap: Option<(Asset, Asset)>,
+    /// The assets that will be provided after the swap
+    pub provide_assets: AssetList,
+    /// The reserves of the pool after the swap
+    pub reserves: Vec<Asset>,
+    /// The amount the swap would return at the spot price of the pool before
+    /// it, which the `max_spread` of the swap is measured against. Zero if no
+    /// swap is needed.
+    pub spot_return: Uint128,
+}
+
+/// Plans the balancing swap for providing `assets` to a pool balanced with
+/// `strategy`, which currently has the reserves `pool_reserves`.
+pub fn plan_balancing_provide(
+    strategy: &dyn BalancingStrategy,
+    pool_reserves: Vec<Asset>,
+    assets: AssetList,
+) -> Result<BalancingProvidePlan, ContractError> {
+    // Pools with more than two assets that accept liquidity in any ratio get
+    // all pool assets as they are, filling in zero amounts for the ones not
+    // sent, since the balancing swap is only calculated for two assets
+    if pool_reserves.len() > 2 && strategy.accepts_any_ratio() {
+        let provide_assets: Vec<Asset> = pool_reserves
+            .iter()
+            .map(|reserve| Asset::new(reserve.info.clone(), find_amount(&assets, &reserve.info)))
+            .collect();
+        return Ok(BalancingProvidePlan {
+            swap: None,
+            provide_assets: provide_assets.into(),
+            reserves: pool_reserves,
+            spot_return: Uint128::zero(),
+        });
+    }
+    if assets.len() > 2 || pool_reserves.len() > 2 {
+        return Err(ContractError::MoreThanTwoAssets {});
+    }
+
+    // Empty pools accept liquidity in any ratio, which then sets the price,
+    // but they need both assets
+    if pool_reserves[0].amount.is_zero() || pool_reserves[1].amount.is_zero() {
+        if assets.len() != 2 {
+            return Err(ContractError::InvalidBootstrapAssets {});
+        }
+        return Ok(BalancingProvidePlan {
+            swap: None,
+            provide_assets: assets,
+            reserves: pool_reserves,
+            spot_return: Uint128::zero(),
+        });
+    }
+
+    // If only one asset is provided in the AssetList, we need to create the
+    // other asset with an empty amount
+    let assets_slice: [Asset; 2] = [
+        Asset::new(
+            pool_reserves[0].info.clone(),
+            find_amount(&assets, &pool_reserves[0].info),
+        ),
+        Asset::new(
+            pool_reserves[1].info.clone(),
+            find_amount(&assets, &pool_reserves[1].info),
+        ),
+    ];
+
+    // Calculate amount of tokens to swap
+    let (offer_asset, return_asset) = strategy.calc_balancing_swap(
+        assets_slice,
+        [pool_reserves[0].clone(), pool_reserves[1].clone()],
+    )?;
+
+    // Pools that accept liquidity in any ratio may not need a swap
+    if offer_asset.amount.is_zero() && strategy.accepts_any_ratio() {
+        return Ok(BalancingProvidePlan {
+            swap: None,
+            provide_assets: assets,
+            reserves: pool_reserves,
+            spot_return: Uint128::zero(),
+        });
+    }
+
+    let spot_return = strategy.calc_spot_return(
+        &offer_asset,
+        [pool_reserves[0].clone(), pool_reserves[1].clone()],
+    )?;
+
+    // Update balances for liquidity provision
+    let mut assets = assets;
+    assets.add(&return_asset)?;
+    assets.deduct(&offer_asset)?;
+
+    // Update reserves with the swap, which may also send part of the
+    // commission out of the pool to the maker
+    let maker_fee_amount = strategy.maker_fee_amount(&return_asset);
+    let mut reserves = pool_reserves;
+    for reserve in reserves.iter_mut() {
+        if reserve.info == offer_asset.info {
+            reserve.amount = reserve.amount.checked_add(offer_asset.amount)?;
+        } else {
+            reserve.amount = reserve
+                .amount
+                .checked_sub(return_asset.amount)?
+                .checked_sub(maker_fee_amount)?;
+        }
+    }
+
+    Ok(BalancingProvidePlan {
+        swap: Some((offer_asset, return_asset)),
+        provide_assets: assets,
+        reserves,
+        spot_return,
+    })
+}
+
+/// Simulates the amount of LP tokens minted when executing `plan` on `pool`,
+/// given the current `total_share` of LP tokens of the pool.
+pub fn simulate_plan_lp_out<P: Pool>(
+    deps: Deps,
+    env: &Env,
+    pool: &P,
+    strategy: &dyn BalancingStrategy,
+    plan: &BalancingProvidePlan,
+    total_share: Uint128,
+) -> Result<Uint128, ContractError> {
+    match plan.swap {
+        // The pool can only simulate with its current reserves, so the
+        // provision after the swap is calculated here
+        Some(_) => strategy.calc_lp_out(
+            [
+                Asset::new(
+                    plan.reserves[0].info.clone(),
+                    find_amount(&plan.provide_assets, &plan.reserves[0].info),
+                ),
+                Asset::new(
+                    plan.reserves[1].info.clone(),
+                    find_amount(&plan.provide_assets, &plan.reserves[1].info),
+                ),
+            ],
+            [plan.reserves[0].clone(), plan.reserves[1].clone()],
+            total_share,
+        ),
+        // No swap is done otherwise, so the pool can simulate the provision
+        // with its current reserves.
+        None => Ok(pool
+            .simulate_provide_liquidity(deps, env, plan.provide_assets.clone())?
+            .amount),
+    }
+}
+
+/// Calculates the amounts of the assets in `plan` that are not backed by the
+/// `lp_out` LP tokens minted, and that will therefore be left over.
+pub fn calc_plan_leftovers(
+    strategy: &dyn BalancingStrategy,
+    plan: &BalancingProvidePlan,
+    lp_out: Uint128,
+    total_share: Uint128,
+) -> Result<AssetList, ContractError> {
+    let mut leftovers = AssetList::new();
+
+    // Pools that accept liquidity in any ratio use all assets, as does the
+    // first provision to an empty pool
+    if total_share.is_zero() || strategy.accepts_any_ratio() {
+        return Ok(leftovers);
+    }
+    for reserve in plan.reserves.iter() {
+        let used_amount = lp_out.multiply_ratio(reserve.amount, total_share);
+        let leftover_amount = find_amount(&plan.provide_assets, &reserve.info)
+            .checked_sub(used_amount)
+            .unwrap_or_default();
+        if leftover_amount > Uint128::zero() {
+            leftovers.add(&Asset::new(reserve.info.clone(), leftover_amount))?;
+        }
+    }
+
+    Ok(leftovers)
+}
+
+/// Plans the swap needed to split the value of the assets `withdrawn` from a
+/// pool balanced with `strategy`, which now has the reserves `pool_reserves`,
+/// according to `weights`. Both `withdrawn` and `weights` must be in the same
+/// order as the reserves. Returns `None` if no swap is needed.
+pub fn plan_weighted_withdraw_swap(
+    strategy: &dyn BalancingStrategy,
+    pool_reserves: [Asset; 2],
+    withdrawn: [Asset; 2],
+    weights: [Decimal; 2],
+) -> Result<Option<(Asset, Asset)>, ContractError> {
+    let (offer_asset, return_asset) =
+        strategy.calc_weighted_swap(withdrawn, weights, pool_reserves)?;
+
+    if offer_asset.amount.is_zero() {
+        return Ok(None);
+    }
+    Ok(Some((offer_asset, return_asset)))
+}
+
+/// Returns the amount of `info` in `assets`, or zero if it is not in the list
+pub fn find_amount(assets: &AssetList, info: &AssetInfo) -> Uint128 {
+    assets
+        .find(info)
+        .map(|asset| asset.amount)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use cosmwasm_std::{Decimal, Uint128};
+    use cw_asset::{Asset, AssetInfo, AssetList};
+
+    use super::{calc_plan_leftovers, plan_balancing_provide, BalancingProvidePlan};
+    use crate::strategy::astroport::{StableStrategy, XykStrategy};
+    use crate::ContractError;
+
+    fn xyk_strategy() -> XykStrategy {
+        XykStrategy {
+            fee: Decimal::from_str("0.003").unwrap(),
+            maker_fee: Decimal::zero(),
+        }
+    }
+
+    fn stable_strategy(denoms: &[&str]) -> StableStrategy {
+        StableStrategy {
+            amp: Decimal::from_str("10").unwrap(),
+            fee: Decimal::from_str("0.0005").unwrap(),
+            maker_fee: Decimal::zero(),
+            precisions: denoms
+                .iter()
+                .map(|denom| (AssetInfo::native(*denom), 6))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_calc_plan_leftovers() {
+        let plan = BalancingProvidePlan {
+            swap: None,
+            provide_assets: AssetList::from(vec![
+                Asset::native("uatom", 1_000_005u128),
+                Asset::native("uosmo", 2_000_000u128),
+            ]),
+            reserves: vec![
+                Asset::native("uatom", 1_000_000_000u128),
+                Asset::native("uosmo", 2_000_000_000u128),
+            ],
+            spot_return: Uint128::zero(),
+        };
+        let total_share = Uint128::from(1_000_000_000u128);
+
+        let leftovers = calc_plan_leftovers(
+            &xyk_strategy(),
+            &plan,
+            Uint128::from(1_000_000u128),
+            total_share,
+        )
+        .unwrap();
+        assert_eq!(
+            leftovers,
+            AssetList::from(vec![Asset::native("uatom", 5u128)])
+        );
+
+        // Stable pools use all of the provided assets
+        let leftovers = calc_plan_leftovers(
+            &stable_strategy(&["uatom", "uosmo"]),
+            &plan,
+            Uint128::from(1_000_000u128),
+            total_share,
+        )
+        .unwrap();
+        assert_eq!(leftovers, AssetList::new());
+    }
+
+    #[test]
+    fn test_plan_balancing_provide_multi_asset_stable() {
+        let pool_reserves = vec![
+            Asset::native("uusdc", 1_000_000u128),
+            Asset::native("uusdt", 1_000_000u128),
+            Asset::native("udai", 1_000_000u128),
+        ];
+
+        // Assets not sent are provided with a zero amount
+        let plan = plan_balancing_provide(
+            &stable_strategy(&["uusdc", "uusdt", "udai"]),
+            pool_reserves.clone(),
+            vec![Asset::native("uusdt", 1_000u128)].into(),
+        )
+        .unwrap();
+        assert!(plan.swap.is_none());
+        assert_eq!(
+            plan.provide_assets,
+            vec![
+                Asset::native("uusdc", Uint128::zero()),
+                Asset::native("uusdt", 1_000u128),
+                Asset::native("udai", Uint128::zero()),
+            ]
+            .into()
+        );
+
+        // Pools that need a balanced ratio can not have more than two assets
+        let err = plan_balancing_provide(
+            &xyk_strategy(),
+            pool_reserves,
+            vec![Asset::native("uusdt", 1_000u128)].into(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::MoreThanTwoAssets {}));
+    }
+
+    #[test]
+    fn test_plan_balancing_provide_spot_return() {
+        let pool_reserves = vec![
+            Asset::native("uatom", 1_000_000_000_000u128),
+            Asset::native("uosmo", 2_000_000_000_000u128),
+        ];
+        let assets: AssetList = vec![Asset::native("uatom", 2_000_000u128)].into();
+
+        // Constant product pools are priced at the ratio of the reserves
+        let plan = plan_balancing_provide(&xyk_strategy(), pool_reserves.clone(), assets).unwrap();
+        let (offer_asset, return_asset) = plan.swap.unwrap();
+        assert_eq!(plan.spot_return, offer_asset.amount * Uint128::from(2u8));
+        assert!(return_asset.amount < plan.spot_return);
+
+        // No swap, no spot return
+        let plan = plan_balancing_provide(
+            &stable_strategy(&["uatom", "uosmo"]),
+            pool_reserves,
+            vec![
+                Asset::native("uatom", 1_000u128),
+                Asset::native("uosmo", 2_000u128),
+            ]
+            .into(),
+        )
+        .unwrap();
+        assert!(plan.swap.is_none());
+        assert_eq!(plan.spot_return, Uint128::zero());
+    }
+
+    #[test]
+    fn test_plan_balancing_provide_maker_fee() {
+        let pool_reserves = vec![
+            Asset::native("uatom", 1_000_000_000_000u128),
+            Asset::native("uosmo", 1_000_000_000_000u128),
+        ];
+        let assets: AssetList = vec![Asset::native("uosmo", 2_000_000_000u128)].into();
+
+        for maker_fee in [Decimal::from_ratio(1u128, 3u128), Decimal::zero()] {
+            let strategy = XykStrategy {
+                maker_fee,
+                ..xyk_strategy()
+            };
+            let plan =
+                plan_balancing_provide(&strategy, pool_reserves.clone(), assets.clone()).unwrap();
+            let (offer_asset, return_asset) = plan.swap.unwrap();
+            assert_eq!(
+                plan.reserves[1].amount,
+                pool_reserves[1].amount + offer_asset.amount
+            );
+
+            // The ask reserve loses the amount swapped out before fees, except
+            // for the part of the commission not sent to the maker
+            let ask_reserve = pool_reserves[0].amount;
+            let amount_before_fees = ask_reserve
+                - ask_reserve.multiply_ratio(
+                    pool_reserves[1].amount,
+                    pool_reserves[1].amount + offer_asset.amount,
+                );
+            let commission_amount = amount_before_fees - return_asset.amount;
+            let expected = ask_reserve - return_asset.amount - commission_amount * maker_fee;
+            let diff = if plan.reserves[0].amount > expected {
+                plan.reserves[0].amount - expected
+            } else {
+                expected - plan.reserves[0].amount
+            };
+            assert!(diff <= Uint128::one(), "ask reserve off by {}", diff);
+        }
+    }
+}
@@ -1,21 +1,39 @@
 use apollo_utils::assets::receive_assets;
 use apollo_utils::responses::merge_responses;
+use astroport::factory::PairType as AstroPairType;
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    from_binary, to_binary, Addr, Binary, Deps, DepsMut, Env, Event, MessageInfo, Response, StdResult, Uint128,
+    from_binary, to_binary, Addr, Binary, Coin, Decimal, Decimal256, Deps, DepsMut, Env, Event,
+    MessageInfo, Order, QuerierWrapper, Response, StdError, StdResult, Uint128, Uint256,
 };
-use cw2::set_contract_version;
-use cw_asset::{Asset, AssetList};
-use cw_dex::astroport::helpers::query_fee_info;
-use cw_dex::astroport::msg::PairType;
+use cw2::{get_contract_version, set_contract_version};
+use cw20::Cw20ReceiveMsg;
+use cw_asset::{Asset, AssetInfo, AssetInfoUnchecked, AssetList};
 use cw_dex::astroport::AstroportPool;
 use cw_dex::traits::Pool;
+use semver::Version;
+use serde::Serialize;
 
+use crate::balancing::{
+    calc_plan_leftovers, find_amount, plan_balancing_provide, plan_weighted_withdraw_swap,
+    simulate_plan_lp_out, BalancingProvidePlan,
+};
 use crate::error::ContractError;
-use crate::math::calc_xyk_balancing_swap;
-use crate::msg::{CallbackMsg, ExecuteMsg, InstantiateMsg, QueryMsg};
-use crate::state::ASTROPORT_FACTORY;
+use crate::generator::{deposit_msg, query_deposit};
+use crate::migrations::migrate_from_v0_1;
+use crate::msg::{
+    AddrUpdate, AssetWeight, CallbackMsg, ConfigResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg,
+    MigrateMsg, PauseInfoResponse, QueryMsg, SimulateBalancingProvideLiquidityResponse,
+    SimulateWithdrawLiquidityToRatioResponse, StakingPositionResponse, SwapRoute,
+};
+use crate::pool::{
+    assert_pool_registered, create_pair_msg, query_pair_info, query_registered_pair, resolve_pool,
+    to_astroport_pool,
+};
+use crate::router::{execute_swap_operations_msg, find_route, resolve_routes};
+use crate::state::{Config, CONFIG, OWNER, PAUSED, PAUSED_POOLS, PROPOSED_OWNER};
+use crate::strategy::{BalancingPool, BalancingStrategy};
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:astroport-liquidity-helper";
@@ -30,8 +48,32 @@ pub fn instantiate(
 ) -> Result<Response, ContractError> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
+    let owner = deps.api.addr_validate(&msg.owner)?;
+    OWNER.save(deps.storage, &owner)?;
+
     let astroport_factory = deps.api.addr_validate(&msg.astroport_factory)?;
-    ASTROPORT_FACTORY.save(deps.storage, &astroport_factory)?;
+    let guardian = msg
+        .guardian
+        .map(|guardian| deps.api.addr_validate(&guardian))
+        .transpose()?;
+    let astroport_generator = msg
+        .astroport_generator
+        .map(|generator| deps.api.addr_validate(&generator))
+        .transpose()?;
+    let astroport_router = msg
+        .astroport_router
+        .map(|router| deps.api.addr_validate(&router))
+        .transpose()?;
+    CONFIG.save(
+        deps.storage,
+        &Config {
+            astroport_factory,
+            guardian,
+            astroport_generator,
+            astroport_router,
+        },
+    )?;
+    PAUSED.save(deps.storage, &false)?;
 
     Ok(Response::default())
 }
@@ -49,11 +91,189 @@ pub fn execute(
             min_out,
             pool,
             recipient,
+            max_spread,
+            belief_price,
+            slippage_tolerance,
+            auto_stake,
+            swap_routes,
+        } => {
+            let assets = assets.check(deps.api)?;
+            let pool: AstroportPool = from_binary(&pool)?;
+            assert_not_paused(deps.as_ref(), &pool)?;
+            assert_pool_registered(deps.as_ref(), &pool)?;
+            execute_balancing_provide_liquidity(
+                deps,
+                env,
+                info,
+                assets,
+                AssetList::new(),
+                min_out,
+                pool,
+                recipient,
+                max_spread,
+                belief_price,
+                slippage_tolerance,
+                auto_stake,
+                swap_routes,
+            )
+        }
+        ExecuteMsg::BalancingProvideLiquidityByPair {
+            assets,
+            min_out,
+            pair,
+            recipient,
+            max_spread,
+            belief_price,
+            slippage_tolerance,
+            auto_stake,
+            swap_routes,
+        } => {
+            let assets = assets.check(deps.api)?;
+            let pool = resolve_pool(deps.as_ref(), pair)?;
+            assert_not_paused(deps.as_ref(), &pool)?;
+            execute_balancing_provide_liquidity(
+                deps,
+                env,
+                info,
+                assets,
+                AssetList::new(),
+                min_out,
+                pool,
+                recipient,
+                max_spread,
+                belief_price,
+                slippage_tolerance,
+                auto_stake,
+                swap_routes,
+            )
+        }
+        ExecuteMsg::CreatePairAndProvideLiquidity {
+            assets,
+            pair_type,
+            init_params,
+            min_out,
+            recipient,
         } => {
             let assets = assets.check(deps.api)?;
+            execute_create_pair_and_provide_liquidity(
+                deps,
+                env,
+                info,
+                assets,
+                pair_type,
+                init_params,
+                min_out,
+                recipient,
+            )
+        }
+        ExecuteMsg::Receive(cw20_msg) => execute_receive(deps, env, info, cw20_msg),
+        ExecuteMsg::BalancingWithdrawLiquidity {
+            lp_token,
+            ask_asset,
+            min_out,
+            pool,
+            recipient,
+            swap_routes,
+        } => {
+            let lp_token = lp_token.check(deps.api)?;
+            let ask_asset = ask_asset.check(deps.api)?;
+            let pool: AstroportPool = from_binary(&pool)?;
+            assert_not_paused(deps.as_ref(), &pool)?;
+            assert_pool_registered(deps.as_ref(), &pool)?;
+            execute_balancing_withdraw_liquidity(
+                deps,
+                env,
+                info,
+                lp_token,
+                ask_asset,
+                min_out,
+                pool,
+                recipient,
+                swap_routes,
+            )
+        }
+        ExecuteMsg::BalancingWithdrawLiquidityToRatio {
+            lp_token,
+            weights,
+            min_out,
+            pool,
+            recipient,
+        } => {
+            let lp_token = lp_token.check(deps.api)?;
+            let min_out = min_out.check(deps.api)?;
+            let pool: AstroportPool = from_binary(&pool)?;
+            assert_not_paused(deps.as_ref(), &pool)?;
+            assert_pool_registered(deps.as_ref(), &pool)?;
+            execute_balancing_withdraw_liquidity_to_ratio(
+                deps, env, info, lp_token, weights, min_out, pool, recipient,
+            )
+        }
+        ExecuteMsg::Compound {
+            pool,
+            rewards,
+            min_out,
+            max_spread,
+            swap_routes,
+        } => {
+            let rewards = rewards.check(deps.api)?;
+            let pool: AstroportPool = from_binary(&pool)?;
+            assert_not_paused(deps.as_ref(), &pool)?;
+            assert_pool_registered(deps.as_ref(), &pool)?;
+            execute_compound(
+                deps,
+                env,
+                info,
+                pool,
+                rewards,
+                min_out,
+                max_spread,
+                swap_routes,
+            )
+        }
+        ExecuteMsg::ExitPosition {
+            pool,
+            amount,
+            ask_asset,
+            min_out,
+            recipient,
+            swap_routes,
+        } => {
+            let ask_asset = ask_asset.check(deps.api)?;
             let pool: AstroportPool = from_binary(&pool)?;
-            execute_balancing_provide_liquidity(deps, env, info, assets, min_out, pool, recipient)
+            assert_not_paused(deps.as_ref(), &pool)?;
+            assert_pool_registered(deps.as_ref(), &pool)?;
+            execute_exit_position(
+                deps,
+                env,
+                info,
+                pool,
+                amount,
+                ask_asset,
+                min_out,
+                recipient,
+                swap_routes,
+            )
+        }
+        ExecuteMsg::UpdateConfig {
+            astroport_factory,
+            guardian,
+            astroport_generator,
+            astroport_router,
+        } => execute_update_config(
+            deps,
+            info,
+            astroport_factory,
+            guardian,
+            astroport_generator,
+            astroport_router,
+        ),
+        ExecuteMsg::SetPaused { paused } => execute_set_paused(deps, info, paused),
+        ExecuteMsg::SetPoolPaused { pair_addr, paused } => {
+            execute_set_pool_paused(deps, info, pair_addr, paused)
         }
+        ExecuteMsg::ProposeNewOwner { owner } => execute_propose_new_owner(deps, info, owner),
+        ExecuteMsg::DropOwnershipProposal {} => execute_drop_ownership_proposal(deps, info),
+        ExecuteMsg::ClaimOwnership {} => execute_claim_ownership(deps, info),
         ExecuteMsg::Callback(msg) => {
             // Only contract can call callbacks
             if info.sender != env.contract.address {
@@ -66,18 +286,121 @@ pub fn execute(
                     min_out,
                     pool,
                 } => execute_callback_provide_liquidity(
-                    deps, env, info, assets, min_out, pool,
+                    deps,
+                    env,
+                    info,
+                    assets,
+                    min_out,
+                    from_binary(&pool)?,
+                ),
+                CallbackMsg::BootstrapPool {
+                    asset_infos,
+                    assets,
+                    min_out,
+                    recipient,
+                } => execute_callback_bootstrap_pool(
+                    deps,
+                    env,
+                    info,
+                    asset_infos,
+                    assets,
+                    min_out,
+                    recipient,
+                ),
+                CallbackMsg::ProvideSwappedAssets {
+                    pool,
+                    balances_before,
+                    min_out,
+                    max_spread,
+                    belief_price,
+                    slippage_tolerance,
+                } => execute_callback_provide_swapped_assets(
+                    deps,
+                    env,
+                    info,
+                    from_binary(&pool)?,
+                    balances_before,
+                    min_out,
+                    max_spread,
+                    belief_price,
+                    slippage_tolerance,
                 ),
                 CallbackMsg::ReturnLpTokens {
                     pool,
                     balance_before,
                     recipient,
+                    auto_stake,
                 } => execute_callback_return_lp_tokens(
+                    deps,
+                    env,
+                    info,
+                    from_binary(&pool)?,
+                    balance_before,
+                    recipient,
+                    auto_stake,
+                ),
+                CallbackMsg::SwapWithdrawnAssets {
+                    pool,
+                    ask_asset_info,
+                    balances_before,
+                    swap_routes,
+                    min_out,
+                    recipient,
+                } => execute_callback_swap_withdrawn_assets(
+                    deps,
+                    env,
+                    info,
+                    pool,
+                    ask_asset_info,
+                    balances_before,
+                    swap_routes,
+                    min_out,
+                    recipient,
+                ),
+                CallbackMsg::RefundLeftovers {
+                    balances_before,
+                    recipient,
+                } => execute_callback_refund_leftovers(deps, env, info, balances_before, recipient),
+                CallbackMsg::SwapWithdrawnAssetsToRatio {
+                    pool,
+                    balances_before,
+                    weights,
+                    min_out,
+                    recipient,
+                } => execute_callback_swap_withdrawn_assets_to_ratio(
                     deps,
                     env,
                     info,
                     pool,
+                    balances_before,
+                    weights,
+                    min_out,
+                    recipient,
+                ),
+                CallbackMsg::ReturnAssets {
+                    balances_before,
+                    min_out,
+                    recipient,
+                } => execute_callback_return_assets(
+                    deps,
+                    env,
+                    info,
+                    balances_before,
+                    min_out,
+                    recipient,
+                ),
+                CallbackMsg::ReturnAsset {
+                    asset_info,
+                    balance_before,
+                    min_out,
+                    recipient,
+                } => execute_callback_return_asset(
+                    deps,
+                    env,
+                    info,
+                    asset_info,
                     balance_before,
+                    min_out,
                     recipient,
                 ),
             }
@@ -85,18 +408,100 @@ pub fn execute(
     }
 }
 
-pub fn execute_balancing_provide_liquidity(
+/// Handles a cw20 sent to the contract with an embedded `Cw20HookMsg`. The
+/// sender of the cw20 is treated as the caller, and only the cw20 sent is
+/// provided. The sender in `cw20_msg` is set by the cw20 contract and is not
+/// authenticated, so no other assets can be pulled on its behalf.
+pub fn execute_receive(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    cw20_msg: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    let received = Asset::cw20(info.sender, cw20_msg.amount);
+    let received_assets: AssetList = vec![received.clone()].into();
+    let info = MessageInfo {
+        sender: deps.api.addr_validate(&cw20_msg.sender)?,
+        funds: info.funds,
+    };
+
+    match from_binary(&cw20_msg.msg)? {
+        Cw20HookMsg::BalancingProvideLiquidity {
+            assets,
+            min_out,
+            pool,
+            recipient,
+            max_spread,
+            belief_price,
+            slippage_tolerance,
+            auto_stake,
+            swap_routes,
+        } => {
+            // A cw20 Send carries no native tokens, so the sent cw20 is the
+            // only asset that can be provided
+            for asset in assets.check(deps.api)?.to_vec() {
+                if asset.info != received.info {
+                    return Err(ContractError::UnexpectedHookAsset {
+                        asset: asset.info.to_string(),
+                    });
+                }
+            }
+            let pool: AstroportPool = from_binary(&pool)?;
+            assert_not_paused(deps.as_ref(), &pool)?;
+            assert_pool_registered(deps.as_ref(), &pool)?;
+            execute_balancing_provide_liquidity(
+                deps,
+                env,
+                info,
+                received_assets.clone(),
+                received_assets,
+                min_out,
+                pool,
+                recipient,
+                max_spread,
+                belief_price,
+                slippage_tolerance,
+                auto_stake,
+                swap_routes,
+            )
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn execute_balancing_provide_liquidity<P: BalancingPool + Serialize>(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    mut assets: AssetList,
+    assets: AssetList,
+    received: AssetList,
     min_out: Uint128,
-    pool: AstroportPool,
+    pool: P,
     recipient: Option<String>,
+    max_spread: Option<Decimal>,
+    belief_price: Option<Decimal>,
+    slippage_tolerance: Option<Decimal>,
+    auto_stake: Option<bool>,
+    swap_routes: Option<Vec<SwapRoute>>,
 ) -> Result<Response, ContractError> {
+    // Make sure there is a generator to stake in before doing anything else
+    let auto_stake = auto_stake.unwrap_or(false);
+    if auto_stake && CONFIG.load(deps.storage)?.astroport_generator.is_none() {
+        return Err(ContractError::GeneratorNotSet {});
+    }
+
+    // Reject duplicate and empty asset lists, as well as native tokens that
+    // would otherwise be left in the contract
+    let assets = assert_valid_provide_assets(assets, &info.funds)?;
+
     // Get response with message to do TransferFrom on any Cw20s and assert that
-    // native tokens have been received already.
-    let receive_res = receive_assets(&info, &env, &assets)?;
+    // native tokens have been received already. Cw20s in `received` have
+    // already been sent to the contract.
+    let mut to_receive = assets.clone();
+    for asset in received.to_vec() {
+        to_receive.deduct(&asset)?;
+    }
+    let receive_res = receive_assets(&info, &env, &to_receive)?;
 
     // Unwrap recipient or use caller's address
     let recipient = recipient.map_or(Ok(info.sender), |x| deps.api.addr_validate(&x))?;
@@ -106,173 +511,2386 @@ pub fn execute_balancing_provide_liquidity(
         .lp_token()
         .query_balance(&deps.querier, env.contract.address.to_string())?;
 
-    match pool.pair_type {
-        PairType::Xyk {} => {
-            // For XYK pools we need to swap some amount of one asset
-            // into the other and then provide liquidity
-            let pool_res = pool.query_pool_info(&deps.querier)?;
+    // Check balances of the pool assets before, excluding any tokens sent with
+    // this message, so that leftovers can be refunded at the end
+    let (pool_reserves, _) = pool.query_reserves(deps.as_ref())?;
+    let balances_before: AssetList = query_balances(
+        &deps.querier,
+        &env.contract.address,
+        pool_reserves.into_iter().map(|reserve| reserve.info),
+    )?
+    .to_vec()
+    .into_iter()
+    .map(|balance| {
+        let received = match &balance.info {
+            AssetInfo::Native(denom) => info
+                .funds
+                .iter()
+                .find(|coin| &coin.denom == denom)
+                .map(|coin| coin.amount)
+                .unwrap_or_default(),
+            AssetInfo::Cw20(_) => find_amount(&received, &balance.info),
+        };
+        Ok(Asset::new(
+            balance.info,
+            balance.amount.checked_sub(received)?,
+        ))
+    })
+    .collect::<StdResult<Vec<_>>>()?
+    .into();
 
-            let pool_reserves: [Asset; 2] =
-                [(&pool_res.assets[0]).into(), (&pool_res.assets[1]).into()];
-            if assets.len() > 2 {
-                return Err(ContractError::MoreThanTwoAssets {});
-            }
+    // Callback to refund any of the pool assets left in the contract
+    let refund_msg = CallbackMsg::RefundLeftovers {
+        balances_before: balances_before.clone(),
+        recipient: recipient.clone(),
+    }
+    .into_cosmos_msg(&env)?;
 
-            // If only one asset is provided in the AssetList, we need to
-            // create the other asset with an empty amount
-            let assets_slice: [Asset; 2] = [
-                assets
-                    .find(&pool_reserves[0].info)
-                    .cloned()
-                    .unwrap_or_else(|| Asset {
-                        info: pool_reserves[0].info.clone(),
-                        amount: Uint128::zero(),
-                    }),
-                assets
-                    .find(&pool_reserves[1].info)
-                    .cloned()
-                    .unwrap_or_else(|| Asset {
-                        info: pool_reserves[1].info.clone(),
-                        amount: Uint128::zero(),
-                    }),
-            ];
-
-            // Get fee amount
-            let fee_info = query_fee_info(
-                &deps.querier,
-                ASTROPORT_FACTORY.load(deps.storage)?,
-                pool.pair_type.clone(),
-            )?;
-            let fee = fee_info.total_fee_rate;
-
-            // Calculate amount of tokens to swap
-            let (offer_asset, return_asset) = calc_xyk_balancing_swap(
-                assets_slice,
-                pool_reserves[0].amount,
-                pool_reserves[1].amount,
-                fee,
-            )?;
-            // Update balances for liquidity provision
-            assets.add(&return_asset)?;
-            assets.deduct(&offer_asset)?;
+    // The strategy is chosen once and used for both the event and the swap
+    let strategy = pool.balancing_strategy(deps.as_ref())?;
+    let action = format!("{}_provide_liquidity", strategy.name());
 
-            let mut response = Response::new();
-            // Create message to swap some of the asset to the other
-            if offer_asset.amount > Uint128::zero() {
-                let swap_res = pool.swap(
-                    deps.as_ref(),
-                    &env,
-                    offer_asset,
-                    return_asset.info.clone(),
-                    Uint128::one(),
-                )?;
-                response = swap_res;
-            }
+    // Split off any assets that are not in the pool
+    let pool_asset_infos: Vec<AssetInfo> = balances_before
+        .to_vec()
+        .into_iter()
+        .map(|asset| asset.info)
+        .collect();
+    let (foreign_assets, pool_assets): (Vec<Asset>, Vec<Asset>) = assets
+        .to_vec()
+        .into_iter()
+        .partition(|asset| !pool_asset_infos.contains(&asset.info));
 
-            // Create message to provide liquidity
-            let provide_msg = CallbackMsg::ProvideLiquidity {
-                assets: assets.clone(),
-                min_out,
-                pool: pool.clone(),
+    let (response, provide_assets, min_out) = if foreign_assets.is_empty() {
+        balancing_provide(
+            deps.as_ref(),
+            &env,
+            &pool,
+            strategy.as_ref(),
+            pool_assets.into(),
+            min_out,
+            max_spread,
+            belief_price,
+            slippage_tolerance,
+        )?
+    } else {
+        // Swap the foreign assets into pool assets through the router, and
+        // then balance and provide the resulting amounts in a callback. Without
+        // a router they can not be swapped, so they are rejected.
+        let router = CONFIG.load(deps.storage)?.astroport_router.ok_or_else(|| {
+            ContractError::AssetNotInPool {
+                asset: foreign_assets[0].info.to_string(),
             }
-            .into_cosmos_msg(&env)?;
-            response = response.add_message(provide_msg);
+        })?;
+        let swap_routes = swap_routes.unwrap_or_default();
+        let mut response = Response::new();
+        for asset in foreign_assets.iter() {
+            let (operations, minimum_receive) =
+                find_route(deps.as_ref(), &asset.info, &pool_asset_infos, &swap_routes)?;
+            response = response.add_message(execute_swap_operations_msg(
+                &router,
+                asset,
+                operations,
+                minimum_receive,
+                max_spread,
+            )?);
+        }
 
-            // Callback to return LP tokens
-            let callback_msg = CallbackMsg::ReturnLpTokens {
-                pool,
-                balance_before: lp_token_balance,
-                recipient,
-            }
-            .into_cosmos_msg(&env)?;
+        let provide_msg = CallbackMsg::ProvideSwappedAssets {
+            pool: to_binary(&pool)?,
+            balances_before,
+            min_out,
+            max_spread,
+            belief_price,
+            slippage_tolerance,
+        }
+        .into_cosmos_msg(&env)?;
+        (response.add_message(provide_msg), assets, min_out)
+    };
 
-            let event =
-                Event::new("apollo/astroport-liquidity-helper/execute_balancing_provide_liquidity")
-                    .add_attribute("action", "xyk_provide_liquidity")
-                    .add_attribute("assets", assets.to_string())
-                    .add_attribute("min_out", min_out);
+    // Callback to return or stake LP tokens
+    let callback_msg = CallbackMsg::ReturnLpTokens {
+        pool: to_binary(&pool)?,
+        balance_before: lp_token_balance,
+        recipient,
+        auto_stake,
+    }
+    .into_cosmos_msg(&env)?;
 
-            Ok(merge_responses(vec![receive_res, response])
-                .add_message(callback_msg)
-                .add_event(event))
-        }
-        PairType::Stable {} => {
-            // For stable pools we are allowed to provide liquidity in any ratio,
-            // so we simply provide liquidity with all passed assets.
-            let provide_liquidity_res =
-                pool.provide_liquidity(deps.as_ref(), &env, assets.clone(), min_out)?;
-
-            // Callback to return LP tokens
-            let callback_msg = CallbackMsg::ReturnLpTokens {
-                pool,
-                balance_before: lp_token_balance,
-                recipient,
-            }
-            .into_cosmos_msg(&env)?;
+    let event = Event::new("apollo/astroport-liquidity-helper/execute_balancing_provide_liquidity")
+        .add_attribute("action", action)
+        .add_attribute("assets", provide_assets.to_string())
+        .add_attribute("min_out", min_out)
+        .add_attribute("auto_stake", auto_stake.to_string());
 
-            let event =
-                Event::new("apollo/astroport-liquidity-helper/execute_balancing_provide_liquidity")
-                    .add_attribute("action", "stable_provide_liquidity")
-                    .add_attribute("assets", assets.to_string())
-                    .add_attribute("min_out", min_out);
+    Ok(merge_responses(vec![receive_res, response])
+        .add_message(refund_msg)
+        .add_message(callback_msg)
+        .add_event(event))
+}
 
-            Ok(merge_responses(vec![receive_res, provide_liquidity_res])
-                .add_message(callback_msg)
-                .add_event(event))
-        }
-        PairType::Custom(_) => Err(ContractError::CustomPairType {}),
+#[allow(clippy::too_many_arguments)]
+pub fn execute_create_pair_and_provide_liquidity(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    assets: AssetList,
+    pair_type: AstroPairType,
+    init_params: Option<Binary>,
+    min_out: Uint128,
+    recipient: Option<String>,
+) -> Result<Response, ContractError> {
+    // There is no pool to check the pause for yet, only the whole contract
+    if PAUSED.may_load(deps.storage)?.unwrap_or_default() {
+        return Err(ContractError::Paused {});
+    }
+
+    // The initial liquidity sets the price, so both assets are needed
+    let assets = assert_valid_provide_assets(assets, &info.funds)?;
+    if assets.len() != 2 {
+        return Err(ContractError::InvalidBootstrapAssets {});
+    }
+    let asset_infos = [
+        assets.to_vec()[0].info.clone(),
+        assets.to_vec()[1].info.clone(),
+    ];
+
+    // Get response with message to do TransferFrom on any Cw20s and assert that
+    // native tokens have been received already.
+    let receive_res = receive_assets(&info, &env, &assets)?;
+
+    // Unwrap recipient or use caller's address
+    let recipient = recipient.map_or(Ok(info.sender), |x| deps.api.addr_validate(&x))?;
+
+    // Create the pair unless the factory already has one for the assets, in
+    // which case the callback makes sure that it is still empty
+    let factory = CONFIG.load(deps.storage)?.astroport_factory;
+    let mut response = Response::new();
+    if query_registered_pair(&deps.querier, &factory, &asset_infos)?.is_none() {
+        response = response.add_message(create_pair_msg(
+            &factory,
+            pair_type.clone(),
+            &asset_infos,
+            init_params,
+        )?);
+    }
+
+    // Callback to provide the initial liquidity once the pair exists
+    let callback_msg = CallbackMsg::BootstrapPool {
+        asset_infos,
+        assets: assets.clone(),
+        min_out,
+        recipient,
     }
+    .into_cosmos_msg(&env)?;
+
+    let event =
+        Event::new("apollo/astroport-liquidity-helper/execute_create_pair_and_provide_liquidity")
+            .add_attribute("pair_type", pair_type.to_string())
+            .add_attribute("assets", assets.to_string())
+            .add_attribute("min_out", min_out);
+
+    Ok(merge_responses(vec![receive_res, response])
+        .add_message(callback_msg)
+        .add_event(event))
 }
 
-/// CallbackMsg handler to provide liquidity with the given assets. This needs
-/// to be a callback, rather than doing in the first ExecuteMsg, because
-/// pool.provide_liquidity does a simulation with current reserves, and we do a
-/// swap in the top level ExecuteMsg, which means the reserves would be wrong in
-/// the provide liquidity simulation.
-pub fn execute_callback_provide_liquidity(
+#[allow(clippy::too_many_arguments)]
+pub fn execute_balancing_withdraw_liquidity(
     deps: DepsMut,
     env: Env,
-    _info: MessageInfo,
-    assets: AssetList,
+    info: MessageInfo,
+    lp_token: Asset,
+    ask_asset_info: AssetInfo,
     min_out: Uint128,
     pool: AstroportPool,
+    recipient: Option<String>,
+    swap_routes: Option<Vec<SwapRoute>>,
 ) -> Result<Response, ContractError> {
-    let res = pool.provide_liquidity(deps.as_ref(), &env, assets.clone(), min_out)?;
+    // Make sure the LP token belongs to the pool
+    assert_lp_token(&pool, &lp_token)?;
 
-    let event = Event::new("apollo/astroport-liquidity-helper/execute_callback_provide_liquidity")
-        .add_attribute("assets", assets.to_string());
+    let pool_asset_infos = query_pool_asset_infos(deps.as_ref(), &pool)?;
 
-    Ok(res.add_event(event))
+    // Get response with message to do TransferFrom on the LP token if it is a
+    // Cw20, or assert that it has been received if it is a native token.
+    let receive_res = receive_assets(&info, &env, &vec![lp_token.clone()].into())?;
+
+    // Unwrap recipient or use caller's address
+    let recipient = recipient.map_or(Ok(info.sender), |x| deps.api.addr_validate(&x))?;
+
+    let withdraw_res = withdraw_and_swap(
+        deps.as_ref(),
+        &env,
+        pool,
+        lp_token.clone(),
+        ask_asset_info,
+        pool_asset_infos,
+        swap_routes.unwrap_or_default(),
+        min_out,
+        recipient,
+    )?;
+
+    let event =
+        Event::new("apollo/astroport-liquidity-helper/execute_balancing_withdraw_liquidity")
+            .add_attribute("lp_token", lp_token.to_string())
+            .add_attribute("min_out", min_out);
+
+    Ok(merge_responses(vec![receive_res, withdraw_res]).add_event(event))
 }
 
-pub fn execute_callback_return_lp_tokens(
+#[allow(clippy::too_many_arguments)]
+pub fn execute_balancing_withdraw_liquidity_to_ratio(
     deps: DepsMut,
     env: Env,
-    _info: MessageInfo,
+    info: MessageInfo,
+    lp_token: Asset,
+    weights: Vec<AssetWeight>,
+    min_out: AssetList,
     pool: AstroportPool,
-    balance_before: Uint128,
-    recipient: Addr,
+    recipient: Option<String>,
 ) -> Result<Response, ContractError> {
-    let lp_token = pool.lp_token();
-    let lp_token_balance = lp_token.query_balance(&deps.querier, env.contract.address)?;
+    // Make sure the LP token belongs to the pool
+    assert_lp_token(&pool, &lp_token)?;
 
-    let return_amount = lp_token_balance.checked_sub(balance_before)?;
-    let return_asset = Asset::new(lp_token, return_amount);
-    let msg = return_asset.transfer_msg(&recipient)?;
+    let pool_asset_infos = query_pool_asset_infos(deps.as_ref(), &pool)?;
+    let weights = check_weights(deps.as_ref(), &pool_asset_infos, weights)?;
 
-    let event = Event::new("apollo/astroport-liquidity-helper/execute_callback_return_lp_tokens")
-        .add_attribute("return_asset", return_asset.to_string())
-        .add_attribute("recipient", recipient);
+    // Get response with message to do TransferFrom on the LP token if it is a
+    // Cw20, or assert that it has been received if it is a native token.
+    let receive_res = receive_assets(&info, &env, &vec![lp_token.clone()].into())?;
 
-    Ok(Response::new().add_message(msg).add_event(event))
-}
+    // Unwrap recipient or use caller's address
+    let recipient = recipient.map_or(Ok(info.sender), |x| deps.api.addr_validate(&x))?;
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::AstroportFactory {} => to_binary(&ASTROPORT_FACTORY.load(deps.storage)?),
+    // Check balances of the pool assets before withdrawing, to pass into
+    // callback
+    let balances_before = query_balances(&deps.querier, &env.contract.address, pool_asset_infos)?;
+
+    let withdraw_res = pool.withdraw_liquidity(deps.as_ref(), &env, lp_token.clone())?;
+
+    // Callback to swap the withdrawn assets into the target ratio
+    let callback_msg = CallbackMsg::SwapWithdrawnAssetsToRatio {
+        pool,
+        balances_before,
+        weights,
+        min_out: min_out.clone(),
+        recipient,
     }
+    .into_cosmos_msg(&env)?;
+
+    let event = Event::new(
+        "apollo/astroport-liquidity-helper/execute_balancing_withdraw_liquidity_to_ratio",
+    )
+    .add_attribute("lp_token", lp_token.to_string())
+    .add_attribute("weights", format!("{},{}", weights[0], weights[1]))
+    .add_attribute("min_out", min_out.to_string());
+
+    Ok(merge_responses(vec![receive_res, withdraw_res])
+        .add_message(callback_msg)
+        .add_event(event))
 }
 
-#[cfg(test)]
-mod tests {}
+/// Withdraws liquidity with `amount` of the caller's LP tokens of `pool` and
+/// swaps the withdrawn assets into the ask asset. Unstaking the LP tokens is
+/// left to the caller, since the generator only lets stakers withdraw their
+/// own deposits.
+#[allow(clippy::too_many_arguments)]
+pub fn execute_exit_position(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    pool: AstroportPool,
+    amount: Uint128,
+    ask_asset_info: AssetInfo,
+    min_out: Uint128,
+    recipient: Option<String>,
+    swap_routes: Option<Vec<SwapRoute>>,
+) -> Result<Response, ContractError> {
+    let lp_token = Asset::new(pool.lp_token(), amount);
+
+    let event = Event::new("apollo/astroport-liquidity-helper/execute_exit_position")
+        .add_attribute("lp_token", lp_token.to_string())
+        .add_attribute("min_out", min_out);
+
+    let response = execute_balancing_withdraw_liquidity(
+        deps,
+        env,
+        info,
+        lp_token,
+        ask_asset_info,
+        min_out,
+        pool,
+        recipient,
+        swap_routes,
+    )?;
+
+    Ok(response.add_event(event))
+}
+
+/// Provides `rewards` as liquidity and stakes the LP tokens received for the
+/// caller. Claiming the rewards is left to the caller, since the generator
+/// only pays them out to the staker. `max_spread` is measured against the
+/// pool price at execution, so only `min_out` protects against a price that
+/// was moved before.
+#[allow(clippy::too_many_arguments)]
+pub fn execute_compound(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    pool: AstroportPool,
+    rewards: AssetList,
+    min_out: Uint128,
+    max_spread: Decimal,
+    swap_routes: Option<Vec<SwapRoute>>,
+) -> Result<Response, ContractError> {
+    let event = Event::new("apollo/astroport-liquidity-helper/execute_compound")
+        .add_attribute("pair_addr", pool.pair_addr.to_string())
+        .add_attribute("rewards", rewards.to_string())
+        .add_attribute("min_out", min_out);
+
+    let response = execute_balancing_provide_liquidity(
+        deps,
+        env,
+        info,
+        rewards,
+        AssetList::new(),
+        min_out,
+        pool,
+        None,
+        Some(max_spread),
+        None,
+        None,
+        Some(true),
+        swap_routes,
+    )?;
+
+    Ok(response.add_event(event))
+}
+
+/// Returns the asset infos of `pool`
+fn query_pool_asset_infos(
+    deps: Deps,
+    pool: &AstroportPool,
+) -> Result<Vec<AssetInfo>, ContractError> {
+    let pool_res = pool.query_pool_info(&deps.querier)?;
+    Ok(pool_res
+        .assets
+        .iter()
+        .map(|asset| Asset::from(asset).info)
+        .collect())
+}
+
+/// Asserts that `lp_token` is the LP token of `pool`
+fn assert_lp_token(pool: &AstroportPool, lp_token: &Asset) -> Result<(), ContractError> {
+    if lp_token.info != pool.lp_token() {
+        return Err(ContractError::InvalidLpToken {
+            expected: pool.lp_token().to_string(),
+            actual: lp_token.info.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Returns the weights in the same order as `pool_asset_infos`, after asserting
+/// that they are all for pool assets and add up to one.
+fn check_weights(
+    deps: Deps,
+    pool_asset_infos: &[AssetInfo],
+    weights: Vec<AssetWeight>,
+) -> Result<[Decimal; 2], ContractError> {
+    // Withdrawing to a ratio is only supported for pools with two assets
+    if pool_asset_infos.len() > 2 {
+        return Err(ContractError::MoreThanTwoAssets {});
+    }
+
+    let mut checked = [Decimal::zero(), Decimal::zero()];
+    for weight in weights {
+        let info = weight.info.check(deps.api)?;
+        let index = pool_asset_infos
+            .iter()
+            .position(|pool_asset_info| pool_asset_info == &info)
+            .ok_or_else(|| ContractError::AssetNotInPool {
+                asset: info.to_string(),
+            })?;
+        checked[index] = checked[index].checked_add(weight.weight)?;
+    }
+
+    if checked[0].checked_add(checked[1])? != Decimal::one() {
+        return Err(ContractError::InvalidWeights {});
+    }
+    Ok(checked)
+}
+
+/// Returns the router swap routes for swapping the withdrawn pool assets into
+/// `ask_asset_info`, after asserting that the router is set if any are needed.
+fn resolve_withdraw_routes(
+    deps: Deps,
+    pool_asset_infos: &[AssetInfo],
+    ask_asset_info: &AssetInfo,
+    swap_routes: &[SwapRoute],
+) -> Result<Vec<SwapRoute>, ContractError> {
+    let routes = resolve_routes(deps, pool_asset_infos, ask_asset_info, swap_routes)?;
+    if !routes.is_empty() && CONFIG.load(deps.storage)?.astroport_router.is_none() {
+        return Err(ContractError::RouterNotSet {});
+    }
+    Ok(routes)
+}
+
+/// Returns a response withdrawing liquidity with `lp_token`, which the
+/// contract must hold by the time the response is executed, and swapping the
+/// withdrawn assets into `ask_asset_info`.
+#[allow(clippy::too_many_arguments)]
+fn withdraw_and_swap(
+    deps: Deps,
+    env: &Env,
+    pool: AstroportPool,
+    lp_token: Asset,
+    ask_asset_info: AssetInfo,
+    mut pool_asset_infos: Vec<AssetInfo>,
+    swap_routes: Vec<SwapRoute>,
+    min_out: Uint128,
+    recipient: Addr,
+) -> Result<Response, ContractError> {
+    let swap_routes =
+        resolve_withdraw_routes(deps, &pool_asset_infos, &ask_asset_info, &swap_routes)?;
+
+    // Check balances of the pool assets and the ask asset before withdrawing,
+    // to pass into callback
+    if !pool_asset_infos.contains(&ask_asset_info) {
+        pool_asset_infos.push(ask_asset_info.clone());
+    }
+    let balances_before = query_balances(&deps.querier, &env.contract.address, pool_asset_infos)?;
+
+    let withdraw_res = pool.withdraw_liquidity(deps, env, lp_token)?;
+
+    // Callback to swap the withdrawn assets into the ask asset
+    let callback_msg = CallbackMsg::SwapWithdrawnAssets {
+        pool,
+        ask_asset_info,
+        balances_before,
+        swap_routes,
+        min_out,
+        recipient,
+    }
+    .into_cosmos_msg(env)?;
+
+    Ok(withdraw_res.add_message(callback_msg))
+}
+
+/// Returns a response balancing `assets` with `strategy` and providing them as
+/// liquidity to `pool`, together with the assets provided after the balancing swap and the
+/// minimum amount of LP tokens to receive after applying `slippage_tolerance`.
+#[allow(clippy::too_many_arguments)]
+fn balancing_provide<P: BalancingPool + Serialize>(
+    deps: Deps,
+    env: &Env,
+    pool: &P,
+    strategy: &dyn BalancingStrategy,
+    assets: AssetList,
+    min_out: Uint128,
+    max_spread: Option<Decimal>,
+    belief_price: Option<Decimal>,
+    slippage_tolerance: Option<Decimal>,
+) -> Result<(Response, AssetList, Uint128), ContractError> {
+    // Plan the swap needed to balance the assets
+    let (pool_reserves, total_share) = pool.query_reserves(deps)?;
+    let plan = plan_balancing_provide(strategy, pool_reserves, assets)?;
+
+    // If a slippage tolerance is given, simulate the amount of LP tokens
+    // received from the reserves after the balancing swap
+    let min_out = match slippage_tolerance {
+        Some(slippage_tolerance) => {
+            let expected_lp = simulate_plan_lp_out(deps, env, pool, strategy, &plan, total_share)?;
+            apply_slippage_tolerance(min_out, expected_lp, slippage_tolerance)?
+        }
+        None => min_out,
+    };
+
+    let response = execute_plan(deps, env, pool, &plan, min_out, max_spread, belief_price)?;
+
+    Ok((response, plan.provide_assets, min_out))
+}
+
+/// Returns a response executing the balancing swap and liquidity provision in
+/// `plan`.
+fn execute_plan<P: BalancingPool + Serialize>(
+    deps: Deps,
+    env: &Env,
+    pool: &P,
+    plan: &BalancingProvidePlan,
+    min_out: Uint128,
+    max_spread: Option<Decimal>,
+    belief_price: Option<Decimal>,
+) -> Result<Response, ContractError> {
+    match &plan.swap {
+        Some((offer_asset, return_asset)) => {
+            let mut response = Response::new();
+            // Create message to swap some of the asset to the other
+            if offer_asset.amount > Uint128::zero() {
+                let swap_min_out = calc_swap_min_out(
+                    offer_asset,
+                    return_asset,
+                    plan.spot_return,
+                    max_spread,
+                    belief_price,
+                )?;
+                response = pool.swap(
+                    deps,
+                    env,
+                    offer_asset.clone(),
+                    return_asset.info.clone(),
+                    swap_min_out,
+                )?;
+            }
+
+            // Create message to provide liquidity
+            let provide_msg = CallbackMsg::ProvideLiquidity {
+                assets: plan.provide_assets.clone(),
+                min_out,
+                pool: to_binary(pool)?,
+            }
+            .into_cosmos_msg(env)?;
+            Ok(response.add_message(provide_msg))
+        }
+        // No swap is needed, so we can provide liquidity directly
+        None => Ok(pool.provide_liquidity(deps, env, plan.provide_assets.clone(), min_out)?),
+    }
+}
+
+/// Calculates the minimum amount that the balancing swap of `offer_asset` must
+/// return. The spread is measured against `belief_price` if given, or
+/// otherwise against `spot_return`, the amount the swap would return at the
+/// spot price of the pool before it. If neither `max_spread` nor
+/// `belief_price` is given the swap is not protected, and only the final
+/// `min_out` on LP tokens applies.
+///
+/// Returns an error if `return_asset`, the amount calculated from the current
+/// reserves, is already less than the minimum, so that a swap with too much
+/// price impact, or a pool whose price moved away from `belief_price`, fails
+/// before any messages are dispatched.
+fn calc_swap_min_out(
+    offer_asset: &Asset,
+    return_asset: &Asset,
+    spot_return: Uint128,
+    max_spread: Option<Decimal>,
+    belief_price: Option<Decimal>,
+) -> Result<Uint128, ContractError> {
+    if max_spread.is_none() && belief_price.is_none() {
+        return Ok(Uint128::one());
+    }
+
+    // Same default as the Astroport pair contracts
+    let max_spread = max_spread.unwrap_or_else(|| Decimal::permille(5));
+
+    // The expected return is calculated in 256 bits, which fits any amount
+    // multiplied by the inverse of a `Decimal`, since it may not fit in 128
+    let expected_return = match belief_price {
+        Some(belief_price) => {
+            let inverse_price = belief_price
+                .inv()
+                .ok_or(ContractError::ZeroBeliefPrice {})?;
+            Uint256::from(offer_asset.amount) * Decimal256::from(inverse_price)
+        }
+        None => Uint256::from(spot_return),
+    };
+    let min_return = expected_return * Decimal256::from(Decimal::one().checked_sub(max_spread)?);
+
+    let return_amount = return_asset.amount;
+    if Uint256::from(return_amount) < min_return {
+        return Err(ContractError::MaxSpreadAssertion {
+            min_return,
+            return_amount,
+        });
+    }
+
+    // The minimum is at most the return amount here, so it fits in 128 bits
+    Ok(Uint128::try_from(min_return).map_err(StdError::from)?)
+}
+
+/// Returns the larger of `min_out` and `expected_lp` reduced by
+/// `slippage_tolerance`.
+fn apply_slippage_tolerance(
+    min_out: Uint128,
+    expected_lp: Uint128,
+    slippage_tolerance: Decimal,
+) -> Result<Uint128, ContractError> {
+    let min_lp = expected_lp * Decimal::one().checked_sub(slippage_tolerance)?;
+    Ok(std::cmp::max(min_out, min_lp))
+}
+
+/// Queries the balances of `address` for all of the given assets
+fn query_balances(
+    querier: &QuerierWrapper,
+    address: &Addr,
+    asset_infos: impl IntoIterator<Item = AssetInfo>,
+) -> StdResult<AssetList> {
+    Ok(asset_infos
+        .into_iter()
+        .map(|info| {
+            let amount = info.query_balance(querier, address.to_string())?;
+            Ok(Asset::new(info, amount))
+        })
+        .collect::<StdResult<Vec<_>>>()?
+        .into())
+}
+
+/// Returns an error if `sender` is not the owner of the contract
+fn assert_owner(deps: Deps, sender: &Addr) -> Result<(), ContractError> {
+    if sender != &OWNER.load(deps.storage)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(())
+}
+
+/// Returns an error if pausing or unpausing is not allowed for `sender`. The
+/// owner can do both, while the guardian can only pause.
+fn assert_can_set_paused(deps: Deps, sender: &Addr, paused: bool) -> Result<(), ContractError> {
+    let is_guardian = CONFIG.load(deps.storage)?.guardian.as_ref() == Some(sender);
+    if paused && is_guardian {
+        return Ok(());
+    }
+    assert_owner(deps, sender)
+}
+
+/// Asserts that `assets` has no duplicate entries and at least one non-zero
+/// amount, and that every native token in `funds` is declared in `assets`.
+/// Returns `assets` without the zero-amount entries.
+fn assert_valid_provide_assets(
+    assets: AssetList,
+    funds: &[Coin],
+) -> Result<AssetList, ContractError> {
+    let mut seen: Vec<AssetInfo> = vec![];
+    for asset in assets.to_vec() {
+        if seen.contains(&asset.info) {
+            return Err(ContractError::DuplicateAsset {
+                asset: asset.info.to_string(),
+            });
+        }
+        seen.push(asset.info);
+    }
+
+    let mut assets = assets;
+    assets.purge();
+    if assets.len() == 0 {
+        return Err(ContractError::NoAssetsProvided {});
+    }
+
+    for coin in funds {
+        let declared = find_amount(&assets, &AssetInfo::Native(coin.denom.clone()));
+        if coin.amount > declared {
+            return Err(ContractError::UndeclaredFunds {
+                denom: coin.denom.clone(),
+                amount: coin.amount - declared,
+            });
+        }
+    }
+
+    Ok(assets)
+}
+
+/// Returns an error if zap messages are paused for the whole contract or for
+/// the given pool
+fn assert_not_paused(deps: Deps, pool: &AstroportPool) -> Result<(), ContractError> {
+    if PAUSED.may_load(deps.storage)?.unwrap_or_default() {
+        return Err(ContractError::Paused {});
+    }
+    if PAUSED_POOLS.has(deps.storage, &pool.pair_addr) {
+        return Err(ContractError::PoolPaused {
+            pair_addr: pool.pair_addr.to_string(),
+        });
+    }
+    Ok(())
+}
+
+pub fn execute_update_config(
+    deps: DepsMut,
+    info: MessageInfo,
+    astroport_factory: Option<String>,
+    guardian: Option<AddrUpdate>,
+    astroport_generator: Option<AddrUpdate>,
+    astroport_router: Option<AddrUpdate>,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+
+    let mut config = CONFIG.load(deps.storage)?;
+    if let Some(astroport_factory) = astroport_factory {
+        config.astroport_factory = deps.api.addr_validate(&astroport_factory)?;
+    }
+    if let Some(guardian) = guardian {
+        config.guardian = apply_addr_update(deps.as_ref(), guardian)?;
+    }
+    if let Some(astroport_generator) = astroport_generator {
+        config.astroport_generator = apply_addr_update(deps.as_ref(), astroport_generator)?;
+    }
+    if let Some(astroport_router) = astroport_router {
+        config.astroport_router = apply_addr_update(deps.as_ref(), astroport_router)?;
+    }
+    CONFIG.save(deps.storage, &config)?;
+
+    let mut event = Event::new("apollo/astroport-liquidity-helper/execute_update_config")
+        .add_attribute("astroport_factory", config.astroport_factory);
+    if let Some(guardian) = config.guardian {
+        event = event.add_attribute("guardian", guardian);
+    }
+    if let Some(astroport_generator) = config.astroport_generator {
+        event = event.add_attribute("astroport_generator", astroport_generator);
+    }
+    if let Some(astroport_router) = config.astroport_router {
+        event = event.add_attribute("astroport_router", astroport_router);
+    }
+
+    Ok(Response::new().add_event(event))
+}
+
+/// Returns the address set by `update`, or `None` if it clears the address
+fn apply_addr_update(deps: Deps, update: AddrUpdate) -> StdResult<Option<Addr>> {
+    match update {
+        AddrUpdate::Set(addr) => Ok(Some(deps.api.addr_validate(&addr)?)),
+        AddrUpdate::Clear {} => Ok(None),
+    }
+}
+
+pub fn execute_set_paused(
+    deps: DepsMut,
+    info: MessageInfo,
+    paused: bool,
+) -> Result<Response, ContractError> {
+    assert_can_set_paused(deps.as_ref(), &info.sender, paused)?;
+
+    PAUSED.save(deps.storage, &paused)?;
+
+    let event = Event::new("apollo/astroport-liquidity-helper/execute_set_paused")
+        .add_attribute("paused", paused.to_string());
+
+    Ok(Response::new().add_event(event))
+}
+
+pub fn execute_set_pool_paused(
+    deps: DepsMut,
+    info: MessageInfo,
+    pair_addr: String,
+    paused: bool,
+) -> Result<Response, ContractError> {
+    assert_can_set_paused(deps.as_ref(), &info.sender, paused)?;
+
+    let pair_addr = deps.api.addr_validate(&pair_addr)?;
+    if paused {
+        PAUSED_POOLS.save(deps.storage, &pair_addr, &true)?;
+    } else {
+        PAUSED_POOLS.remove(deps.storage, &pair_addr);
+    }
+
+    let event = Event::new("apollo/astroport-liquidity-helper/execute_set_pool_paused")
+        .add_attribute("pair_addr", pair_addr)
+        .add_attribute("paused", paused.to_string());
+
+    Ok(Response::new().add_event(event))
+}
+
+pub fn execute_propose_new_owner(
+    deps: DepsMut,
+    info: MessageInfo,
+    owner: String,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+
+    let proposed_owner = deps.api.addr_validate(&owner)?;
+    PROPOSED_OWNER.save(deps.storage, &proposed_owner)?;
+
+    let event = Event::new("apollo/astroport-liquidity-helper/execute_propose_new_owner")
+        .add_attribute("proposed_owner", proposed_owner);
+
+    Ok(Response::new().add_event(event))
+}
+
+pub fn execute_drop_ownership_proposal(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+
+    PROPOSED_OWNER.remove(deps.storage);
+
+    let event = Event::new("apollo/astroport-liquidity-helper/execute_drop_ownership_proposal");
+
+    Ok(Response::new().add_event(event))
+}
+
+pub fn execute_claim_ownership(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let proposed_owner = PROPOSED_OWNER
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NoOwnershipProposal {})?;
+    if info.sender != proposed_owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    OWNER.save(deps.storage, &proposed_owner)?;
+    PROPOSED_OWNER.remove(deps.storage);
+
+    let event = Event::new("apollo/astroport-liquidity-helper/execute_claim_ownership")
+        .add_attribute("owner", proposed_owner);
+
+    Ok(Response::new().add_event(event))
+}
+
+/// CallbackMsg handler to provide liquidity with the given assets. This needs
+/// to be a callback, rather than doing in the first ExecuteMsg, because
+/// pool.provide_liquidity does a simulation with current reserves, and we do a
+/// swap in the top level ExecuteMsg, which means the reserves would be wrong in
+/// the provide liquidity simulation.
+pub fn execute_callback_provide_liquidity(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    assets: AssetList,
+    min_out: Uint128,
+    pool: AstroportPool,
+) -> Result<Response, ContractError> {
+    let res = pool.provide_liquidity(deps.as_ref(), &env, assets.clone(), min_out)?;
+
+    let event = Event::new("apollo/astroport-liquidity-helper/execute_callback_provide_liquidity")
+        .add_attribute("assets", assets.to_string());
+
+    Ok(res.add_event(event))
+}
+
+/// CallbackMsg handler to provide the initial liquidity to the pair registered
+/// in the factory for `asset_infos`, and to return the LP tokens to the
+/// recipient.
+pub fn execute_callback_bootstrap_pool(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    asset_infos: [AssetInfo; 2],
+    assets: AssetList,
+    min_out: Uint128,
+    recipient: Addr,
+) -> Result<Response, ContractError> {
+    let factory = CONFIG.load(deps.storage)?.astroport_factory;
+    let pool = to_astroport_pool(query_pair_info(&deps.querier, &factory, &asset_infos)?);
+
+    // Only empty pools can be bootstrapped, since the assets are provided
+    // without balancing them
+    let pool_res = pool.query_pool_info(&deps.querier)?;
+    if !pool_res.total_share.is_zero() {
+        return Err(ContractError::PoolNotEmpty {
+            pair_addr: pool.pair_addr.to_string(),
+        });
+    }
+
+    // Check lp token balance before, to pass into callback
+    let lp_token_balance = pool
+        .lp_token()
+        .query_balance(&deps.querier, env.contract.address.to_string())?;
+
+    let res = pool.provide_liquidity(deps.as_ref(), &env, assets.clone(), min_out)?;
+
+    // Callback to return LP tokens
+    let callback_msg = CallbackMsg::ReturnLpTokens {
+        pool: to_binary(&pool)?,
+        balance_before: lp_token_balance,
+        recipient,
+        auto_stake: false,
+    }
+    .into_cosmos_msg(&env)?;
+
+    let event = Event::new("apollo/astroport-liquidity-helper/execute_callback_bootstrap_pool")
+        .add_attribute("pair_addr", pool.pair_addr.to_string())
+        .add_attribute("assets", assets.to_string());
+
+    Ok(res.add_message(callback_msg).add_event(event))
+}
+
+/// CallbackMsg handler to balance and provide the increase in the pool asset
+/// balances since `balances_before`, after assets that are not in the pool
+/// have been swapped into pool assets.
+#[allow(clippy::too_many_arguments)]
+pub fn execute_callback_provide_swapped_assets(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    pool: AstroportPool,
+    balances_before: AssetList,
+    min_out: Uint128,
+    max_spread: Option<Decimal>,
+    belief_price: Option<Decimal>,
+    slippage_tolerance: Option<Decimal>,
+) -> Result<Response, ContractError> {
+    let mut assets = AssetList::new();
+    for asset in balances_before.to_vec() {
+        let balance = asset
+            .info
+            .query_balance(&deps.querier, env.contract.address.to_string())?;
+        let amount = balance.checked_sub(asset.amount)?;
+        if amount > Uint128::zero() {
+            assets.add(&Asset::new(asset.info, amount))?;
+        }
+    }
+
+    let strategy = pool.balancing_strategy(deps.as_ref())?;
+    let (response, provide_assets, min_out) = balancing_provide(
+        deps.as_ref(),
+        &env,
+        &pool,
+        strategy.as_ref(),
+        assets,
+        min_out,
+        max_spread,
+        belief_price,
+        slippage_tolerance,
+    )?;
+
+    let event =
+        Event::new("apollo/astroport-liquidity-helper/execute_callback_provide_swapped_assets")
+            .add_attribute("assets", provide_assets.to_string())
+            .add_attribute("min_out", min_out);
+
+    Ok(response.add_event(event))
+}
+
+/// CallbackMsg handler to send the LP tokens minted to the recipient, or if
+/// `auto_stake` is set, to deposit them in the generator on behalf of the
+/// recipient.
+pub fn execute_callback_return_lp_tokens(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    pool: AstroportPool,
+    balance_before: Uint128,
+    recipient: Addr,
+    auto_stake: bool,
+) -> Result<Response, ContractError> {
+    let lp_token = pool.lp_token();
+    let lp_token_balance =
+        lp_token.query_balance(&deps.querier, env.contract.address.to_string())?;
+
+    let return_amount = lp_token_balance.checked_sub(balance_before)?;
+    let return_asset = Asset::new(lp_token.clone(), return_amount);
+
+    let event = Event::new("apollo/astroport-liquidity-helper/execute_callback_return_lp_tokens")
+        .add_attribute("return_asset", return_asset.to_string())
+        .add_attribute("recipient", recipient.to_string())
+        .add_attribute("auto_stake", auto_stake.to_string());
+
+    if !auto_stake {
+        let msg = return_asset.transfer_msg(&recipient)?;
+        return Ok(Response::new().add_message(msg).add_event(event));
+    }
+
+    let generator = CONFIG
+        .load(deps.storage)?
+        .astroport_generator
+        .ok_or(ContractError::GeneratorNotSet {})?;
+
+    // The deposit belongs to the recipient, so any rewards the generator pays
+    // out for it also go to the recipient
+    Ok(Response::new()
+        .add_message(deposit_msg(&generator, &return_asset, &recipient)?)
+        .add_event(event))
+}
+
+/// CallbackMsg handler to swap the assets received from withdrawing liquidity
+/// into the ask asset. The withdrawn amounts are calculated by comparing the
+/// current balances with the balances from before the withdrawal.
+#[allow(clippy::too_many_arguments)]
+pub fn execute_callback_swap_withdrawn_assets(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    pool: AstroportPool,
+    ask_asset_info: AssetInfo,
+    balances_before: AssetList,
+    swap_routes: Vec<SwapRoute>,
+    min_out: Uint128,
+    recipient: Addr,
+) -> Result<Response, ContractError> {
+    let router = CONFIG.load(deps.storage)?.astroport_router;
+    let mut response = Response::new();
+    let mut ask_balance_before = Uint128::zero();
+    for asset in balances_before.to_vec() {
+        if asset.info == ask_asset_info {
+            ask_balance_before = asset.amount;
+            continue;
+        }
+
+        // Swap the withdrawn amount of any other asset into the ask asset
+        let balance = asset
+            .info
+            .query_balance(&deps.querier, env.contract.address.to_string())?;
+        let withdrawn_amount = balance.checked_sub(asset.amount)?;
+        if withdrawn_amount.is_zero() {
+            continue;
+        }
+        let withdrawn_asset = Asset::new(asset.info, withdrawn_amount);
+
+        // Use the router if there is a route for the asset, and otherwise swap
+        // in the pool
+        let route = swap_routes.iter().find(|route| {
+            route.offer_asset_info == AssetInfoUnchecked::from(withdrawn_asset.info.clone())
+        });
+        match route {
+            Some(route) => {
+                let router = router.as_ref().ok_or(ContractError::RouterNotSet {})?;
+                response = response.add_message(execute_swap_operations_msg(
+                    router,
+                    &withdrawn_asset,
+                    route.operations.clone(),
+                    route.minimum_receive,
+                    None,
+                )?);
+            }
+            None => {
+                let swap_res = pool.swap(
+                    deps.as_ref(),
+                    &env,
+                    withdrawn_asset,
+                    ask_asset_info.clone(),
+                    Uint128::one(),
+                )?;
+                response = merge_responses(vec![response, swap_res]);
+            }
+        }
+    }
+
+    // Callback to return the ask asset
+    let callback_msg = CallbackMsg::ReturnAsset {
+        asset_info: ask_asset_info.clone(),
+        balance_before: ask_balance_before,
+        min_out,
+        recipient,
+    }
+    .into_cosmos_msg(&env)?;
+
+    let event =
+        Event::new("apollo/astroport-liquidity-helper/execute_callback_swap_withdrawn_assets")
+            .add_attribute("ask_asset_info", ask_asset_info.to_string());
+
+    Ok(response.add_message(callback_msg).add_event(event))
+}
+
+/// CallbackMsg handler to swap between the assets withdrawn from `pool`, so
+/// that their value is split according to `weights`.
+#[allow(clippy::too_many_arguments)]
+pub fn execute_callback_swap_withdrawn_assets_to_ratio(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    pool: AstroportPool,
+    balances_before: AssetList,
+    weights: [Decimal; 2],
+    min_out: AssetList,
+    recipient: Addr,
+) -> Result<Response, ContractError> {
+    // The reserves are in the same order as the balances and weights
+    let pool_res = pool.query_pool_info(&deps.querier)?;
+    let pool_reserves: [Asset; 2] = [(&pool_res.assets[0]).into(), (&pool_res.assets[1]).into()];
+    let mut withdrawn = vec![];
+    for reserve in pool_reserves.iter() {
+        let balance = reserve
+            .info
+            .query_balance(&deps.querier, env.contract.address.to_string())?;
+        let balance_before = find_amount(&balances_before, &reserve.info);
+        withdrawn.push(Asset::new(
+            reserve.info.clone(),
+            balance.checked_sub(balance_before)?,
+        ));
+    }
+    let withdrawn: [Asset; 2] = [withdrawn[0].clone(), withdrawn[1].clone()];
+
+    let swap = plan_weighted_withdraw_swap(
+        pool.balancing_strategy(deps.as_ref())?.as_ref(),
+        pool_reserves,
+        withdrawn,
+        weights,
+    )?;
+    let mut response = match &swap {
+        Some((offer_asset, return_asset)) => pool.swap(
+            deps.as_ref(),
+            &env,
+            offer_asset.clone(),
+            return_asset.info.clone(),
+            Uint128::one(),
+        )?,
+        None => Response::new(),
+    };
+
+    // Callback to return the assets, checking min_out for each of them
+    let callback_msg = CallbackMsg::ReturnAssets {
+        balances_before,
+        min_out,
+        recipient,
+    }
+    .into_cosmos_msg(&env)?;
+
+    let mut event = Event::new(
+        "apollo/astroport-liquidity-helper/execute_callback_swap_withdrawn_assets_to_ratio",
+    );
+    if let Some((offer_asset, return_asset)) = swap {
+        event = event
+            .add_attribute("offer_asset", offer_asset.to_string())
+            .add_attribute("return_asset", return_asset.to_string());
+    }
+    response = response.add_message(callback_msg).add_event(event);
+
+    Ok(response)
+}
+
+/// CallbackMsg handler to return the increase in the contract's balances of
+/// the given assets to the recipient, after asserting that each is at least
+/// its amount in `min_out`.
+pub fn execute_callback_return_assets(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    balances_before: AssetList,
+    min_out: AssetList,
+    recipient: Addr,
+) -> Result<Response, ContractError> {
+    let mut return_assets = AssetList::new();
+    for asset in balances_before.to_vec() {
+        let balance = asset
+            .info
+            .query_balance(&deps.querier, env.contract.address.to_string())?;
+        let return_amount = balance.checked_sub(asset.amount)?;
+
+        let asset_min_out = find_amount(&min_out, &asset.info);
+        if return_amount < asset_min_out {
+            return Err(ContractError::MinOutNotReceived {
+                min_out: asset_min_out,
+                received: return_amount,
+            });
+        }
+        if return_amount > Uint128::zero() {
+            return_assets.add(&Asset::new(asset.info, return_amount))?;
+        }
+    }
+
+    let msgs = return_assets.transfer_msgs(&recipient)?;
+
+    let event = Event::new("apollo/astroport-liquidity-helper/execute_callback_return_assets")
+        .add_attribute("return_assets", return_assets.to_string())
+        .add_attribute("recipient", recipient);
+
+    Ok(Response::new().add_messages(msgs).add_event(event))
+}
+
+/// CallbackMsg handler to return the increase in the contract's balance of
+/// the given asset to the recipient, after asserting that it is at least
+/// `min_out`.
+pub fn execute_callback_return_asset(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    asset_info: AssetInfo,
+    balance_before: Uint128,
+    min_out: Uint128,
+    recipient: Addr,
+) -> Result<Response, ContractError> {
+    let balance = asset_info.query_balance(&deps.querier, env.contract.address)?;
+
+    let return_amount = balance.checked_sub(balance_before)?;
+    if return_amount < min_out {
+        return Err(ContractError::MinOutNotReceived {
+            min_out,
+            received: return_amount,
+        });
+    }
+    let return_asset = Asset::new(asset_info, return_amount);
+    let msg = return_asset.transfer_msg(&recipient)?;
+
+    let event = Event::new("apollo/astroport-liquidity-helper/execute_callback_return_asset")
+        .add_attribute("return_asset", return_asset.to_string())
+        .add_attribute("recipient", recipient);
+
+    Ok(Response::new().add_message(msg).add_event(event))
+}
+
+/// CallbackMsg handler to send any increase in the contract's balances of the
+/// given assets to the recipient. This refunds what is left over from
+/// providing liquidity due to rounding in the balancing swap.
+pub fn execute_callback_refund_leftovers(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    balances_before: AssetList,
+    recipient: Addr,
+) -> Result<Response, ContractError> {
+    let mut refunded_assets = AssetList::new();
+    for asset in balances_before.to_vec() {
+        let balance = asset
+            .info
+            .query_balance(&deps.querier, env.contract.address.to_string())?;
+        let leftover_amount = balance.checked_sub(asset.amount)?;
+        if leftover_amount > Uint128::zero() {
+            refunded_assets.add(&Asset::new(asset.info, leftover_amount))?;
+        }
+    }
+
+    let msgs = refunded_assets.transfer_msgs(&recipient)?;
+
+    let event = Event::new("apollo/astroport-liquidity-helper/execute_callback_refund_leftovers")
+        .add_attribute("refunded_assets", refunded_assets.to_string())
+        .add_attribute("recipient", recipient);
+
+    Ok(Response::new().add_messages(msgs).add_event(event))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::AstroportFactory {} => to_binary(&CONFIG.load(deps.storage)?.astroport_factory),
+        QueryMsg::PauseInfo {} => to_binary(&PauseInfoResponse {
+            paused: PAUSED.may_load(deps.storage)?.unwrap_or_default(),
+            paused_pools: PAUSED_POOLS
+                .keys(deps.storage, None, None, Order::Ascending)
+                .collect::<StdResult<Vec<_>>>()?,
+        }),
+        QueryMsg::Config {} => to_binary(&ConfigResponse {
+            config: CONFIG.load(deps.storage)?,
+            owner: OWNER.load(deps.storage)?,
+            proposed_owner: PROPOSED_OWNER.may_load(deps.storage)?,
+        }),
+        QueryMsg::SimulateWithdrawLiquidityToRatio {
+            lp_token,
+            weights,
+            pool,
+        } => {
+            let lp_token = lp_token.check(deps.api)?;
+            let pool: AstroportPool = from_binary(&pool)?;
+            assert_pool_registered(deps, &pool)?;
+            to_binary(&query_simulate_withdraw_liquidity_to_ratio(
+                deps, lp_token, weights, pool,
+            )?)
+        }
+        QueryMsg::StakingPosition { lp_token, user } => {
+            let lp_token = lp_token.check(deps.api)?;
+            let user = deps.api.addr_validate(&user)?;
+            to_binary(&query_staking_position(deps, lp_token, user)?)
+        }
+        QueryMsg::SimulateBalancingProvideLiquidity { assets, pool } => {
+            let assets = assets.check(deps.api)?;
+            let pool: AstroportPool = from_binary(&pool)?;
+            assert_pool_registered(deps, &pool)?;
+            to_binary(&query_simulate_balancing_provide_liquidity(
+                deps, env, assets, pool,
+            )?)
+        }
+    }
+}
+
+pub fn query_simulate_withdraw_liquidity_to_ratio(
+    deps: Deps,
+    lp_token: Asset,
+    weights: Vec<AssetWeight>,
+    pool: AstroportPool,
+) -> Result<SimulateWithdrawLiquidityToRatioResponse, ContractError> {
+    assert_lp_token(&pool, &lp_token)?;
+
+    let pool_res = pool.query_pool_info(&deps.querier)?;
+    if pool_res.total_share.is_zero() {
+        return Err(ContractError::PoolEmpty {
+            pair_addr: pool.pair_addr.to_string(),
+        });
+    }
+    let pool_reserves: [Asset; 2] = [(&pool_res.assets[0]).into(), (&pool_res.assets[1]).into()];
+    let pool_asset_infos: Vec<AssetInfo> = pool_reserves
+        .iter()
+        .map(|reserve| reserve.info.clone())
+        .collect();
+    let weights = check_weights(deps, &pool_asset_infos, weights)?;
+
+    // Withdrawing returns a share of the reserves proportional to the LP tokens
+    let withdrawn = pool_reserves.clone().map(|reserve| {
+        let amount = reserve
+            .amount
+            .multiply_ratio(lp_token.amount, pool_res.total_share);
+        Asset::new(reserve.info, amount)
+    });
+    let reserves_after = [
+        Asset::new(
+            pool_reserves[0].info.clone(),
+            pool_reserves[0].amount.checked_sub(withdrawn[0].amount)?,
+        ),
+        Asset::new(
+            pool_reserves[1].info.clone(),
+            pool_reserves[1].amount.checked_sub(withdrawn[1].amount)?,
+        ),
+    ];
+
+    let swap = plan_weighted_withdraw_swap(
+        pool.balancing_strategy(deps)?.as_ref(),
+        reserves_after,
+        withdrawn.clone(),
+        weights,
+    )?;
+
+    let withdrawn_assets = AssetList::from(withdrawn.to_vec());
+    let mut return_assets = withdrawn_assets.clone();
+    let (offer_asset, return_asset) = match swap {
+        Some((offer_asset, return_asset)) => {
+            return_assets.deduct(&offer_asset)?;
+            return_assets.add(&return_asset)?;
+            (Some(offer_asset), Some(return_asset))
+        }
+        None => (None, None),
+    };
+
+    Ok(SimulateWithdrawLiquidityToRatioResponse {
+        withdrawn_assets,
+        offer_asset,
+        return_asset,
+        return_assets,
+    })
+}
+
+pub fn query_staking_position(
+    deps: Deps,
+    lp_token: AssetInfo,
+    user: Addr,
+) -> Result<StakingPositionResponse, ContractError> {
+    let lp_amount = match CONFIG.load(deps.storage)?.astroport_generator {
+        Some(generator) => query_deposit(&deps.querier, &generator, &lp_token, &user)?,
+        None => Uint128::zero(),
+    };
+
+    Ok(StakingPositionResponse { lp_amount })
+}
+
+pub fn query_simulate_balancing_provide_liquidity(
+    deps: Deps,
+    env: Env,
+    assets: AssetList,
+    pool: AstroportPool,
+) -> Result<SimulateBalancingProvideLiquidityResponse, ContractError> {
+    let pool_res = pool.query_pool_info(&deps.querier)?;
+    let pool_reserves: Vec<Asset> = pool_res.assets.iter().map(Asset::from).collect();
+
+    // Swaps through the router can not be simulated here
+    for asset in assets.to_vec() {
+        if pool_reserves
+            .iter()
+            .all(|reserve| reserve.info != asset.info)
+        {
+            return Err(ContractError::AssetNotInPool {
+                asset: asset.info.to_string(),
+            });
+        }
+    }
+
+    // Run the same planning as when executing
+    let strategy = pool.balancing_strategy(deps)?;
+    let plan = plan_balancing_provide(strategy.as_ref(), pool_reserves, assets)?;
+    let lp_tokens = simulate_plan_lp_out(
+        deps,
+        &env,
+        &pool,
+        strategy.as_ref(),
+        &plan,
+        pool_res.total_share,
+    )?;
+    let leftover_assets =
+        calc_plan_leftovers(strategy.as_ref(), &plan, lp_tokens, pool_res.total_share)?;
+
+    let (offer_asset, return_asset) = match plan.swap {
+        Some((offer_asset, return_asset)) => (Some(offer_asset), Some(return_asset)),
+        None => (None, None),
+    };
+
+    Ok(SimulateBalancingProvideLiquidityResponse {
+        offer_asset,
+        return_asset,
+        provide_assets: plan.provide_assets,
+        lp_tokens,
+        leftover_assets,
+    })
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    let stored = get_contract_version(deps.storage)?;
+    if stored.contract != CONTRACT_NAME {
+        return Err(ContractError::InvalidContractName {
+            contract_name: stored.contract,
+        });
+    }
+
+    // Refuse to downgrade
+    let stored_version: Version = stored.version.parse()?;
+    let new_version: Version = CONTRACT_VERSION.parse()?;
+    if stored_version > new_version {
+        return Err(ContractError::MigrationDowngrade {
+            stored_version: stored.version,
+            new_version: CONTRACT_VERSION.to_string(),
+        });
+    }
+
+    // Run the state migrations for every version between the stored and the
+    // new version
+    if stored_version < Version::new(0, 2, 0) {
+        let owner = msg.owner.ok_or_else(|| ContractError::MissingOwner {
+            stored_version: stored.version.clone(),
+        })?;
+        let owner = deps.api.addr_validate(&owner)?;
+        migrate_from_v0_1(deps.storage, owner)?;
+    }
+
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    let event = Event::new("apollo/astroport-liquidity-helper/migrate")
+        .add_attribute("from_version", stored.version)
+        .add_attribute("to_version", CONTRACT_VERSION);
+
+    Ok(Response::new().add_event(event))
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+        MOCK_CONTRACT_ADDR,
+    };
+    use cosmwasm_std::{
+        coin, from_binary, to_binary, Addr, Binary, ContractResult, CosmosMsg, Decimal, OwnedDeps,
+        Response, SystemError, SystemResult, Uint128, Uint256, WasmMsg, WasmQuery,
+    };
+    use cw2::{get_contract_version, set_contract_version};
+    use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+    use cw_asset::{Asset, AssetInfo, AssetInfoUnchecked, AssetList};
+    use cw_dex::astroport::msg::PairType;
+    use cw_dex::astroport::AstroportPool;
+    use cw_storage_plus::Item;
+    use serde::Deserialize;
+
+    use super::{
+        apply_slippage_tolerance, assert_valid_provide_assets, balancing_provide,
+        calc_swap_min_out, check_weights, execute, instantiate, migrate,
+        query_simulate_withdraw_liquidity_to_ratio, CONTRACT_NAME, CONTRACT_VERSION,
+    };
+    use crate::balancing::plan_balancing_provide;
+    use crate::msg::{
+        AddrUpdate, AssetWeight, CallbackMsg, Cw20HookMsg, ExecuteMsg, InstantiateMsg, MigrateMsg,
+    };
+    use crate::state::{CONFIG, OWNER, PROPOSED_OWNER};
+    use crate::strategy::astroport::XykStrategy;
+    use crate::ContractError;
+
+    /// The queries answered by the mocked Astroport contracts
+    #[derive(Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum MockQuery {
+        Pool {},
+        Pair {},
+        FeeInfo {},
+        Balance {},
+    }
+
+    /// Mocks a XYK pair "pair" of 1_000_000_000 uatom and uosmo, registered
+    /// in the factory "factory", whose LP token "lp_token" has a balance of
+    /// `lp_balance` for every address
+    fn mock_astroport(querier: &mut MockQuerier, lp_balance: u128) {
+        querier.update_wasm(move |query| {
+            let response = match query {
+                WasmQuery::Smart { contract_addr, msg } => {
+                    match (contract_addr.as_str(), from_binary::<MockQuery>(msg).ok()) {
+                        ("pair", Some(MockQuery::Pool {})) => Some(
+                            r#"{
+                                "assets": [
+                                    {
+                                        "info": { "native_token": { "denom": "uatom" } },
+                                        "amount": "1000000000"
+                                    },
+                                    {
+                                        "info": { "native_token": { "denom": "uosmo" } },
+                                        "amount": "1000000000"
+                                    }
+                                ],
+                                "total_share": "1000000000"
+                            }"#
+                            .to_string(),
+                        ),
+                        ("factory", Some(MockQuery::Pair {})) => Some(
+                            r#"{
+                                "asset_infos": [
+                                    { "native_token": { "denom": "uatom" } },
+                                    { "native_token": { "denom": "uosmo" } }
+                                ],
+                                "contract_addr": "pair",
+                                "liquidity_token": "lp_token",
+                                "pair_type": { "xyk": {} }
+                            }"#
+                            .to_string(),
+                        ),
+                        ("factory", Some(MockQuery::FeeInfo {})) => Some(
+                            r#"{"fee_address":null,"total_fee_bps":30,"maker_fee_bps":0}"#
+                                .to_string(),
+                        ),
+                        ("lp_token", Some(MockQuery::Balance {})) => {
+                            Some(format!(r#"{{"balance":"{}"}}"#, lp_balance))
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            match response {
+                Some(response) => {
+                    SystemResult::Ok(ContractResult::Ok(Binary::from(response.as_bytes())))
+                }
+                None => SystemResult::Err(SystemError::UnsupportedRequest {
+                    kind: format!("{:?}", query),
+                }),
+            }
+        });
+    }
+
+    /// Returns mock dependencies with the contract instantiated with a
+    /// generator and a router, and the Astroport contracts of
+    /// `mock_astroport`, together with the mocked pool
+    fn setup_xyk_pool() -> (OwnedDeps<MockStorage, MockApi, MockQuerier>, AstroportPool) {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            owner: "owner".to_string(),
+            astroport_factory: "factory".to_string(),
+            guardian: None,
+            astroport_generator: Some("generator".to_string()),
+            astroport_router: Some("router".to_string()),
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        mock_astroport(&mut deps.querier, 0);
+
+        let pool = AstroportPool {
+            pair_addr: Addr::unchecked("pair"),
+            lp_token_addr: Addr::unchecked("lp_token"),
+            pair_type: PairType::Xyk {},
+        };
+        (deps, pool)
+    }
+
+    /// Returns the addresses of the contracts executed by the messages of
+    /// `response`
+    fn executed_contracts(response: &Response) -> Vec<String> {
+        response
+            .messages
+            .iter()
+            .filter_map(|sub_msg| match &sub_msg.msg {
+                CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }) => {
+                    Some(contract_addr.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the callbacks among the messages of `response`
+    fn callbacks(response: &Response) -> Vec<CallbackMsg> {
+        response
+            .messages
+            .iter()
+            .filter_map(|sub_msg| match &sub_msg.msg {
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr, msg, ..
+                }) if contract_addr == MOCK_CONTRACT_ADDR => from_binary::<ExecuteMsg>(msg).ok(),
+                _ => None,
+            })
+            .filter_map(|msg| match msg {
+                ExecuteMsg::Callback(callback) => Some(callback),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_assert_valid_provide_assets() {
+        let uosmo = Asset::native("uosmo", 1_000u128);
+        let uatom = Asset::native("uatom", 1_000u128);
+
+        // Zero-amount entries are dropped
+        let assets = assert_valid_provide_assets(
+            vec![uosmo.clone(), Asset::native("uatom", 0u128)].into(),
+            &[coin(1_000, "uosmo")],
+        )
+        .unwrap();
+        assert_eq!(assets, vec![uosmo.clone()].into());
+
+        let err = assert_valid_provide_assets(
+            vec![uosmo.clone(), uosmo.clone()].into(),
+            &[coin(2_000, "uosmo")],
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::DuplicateAsset { .. }));
+
+        let err = assert_valid_provide_assets(vec![Asset::native("uosmo", 0u128)].into(), &[])
+            .unwrap_err();
+        assert!(matches!(err, ContractError::NoAssetsProvided {}));
+
+        let err = assert_valid_provide_assets(
+            vec![uosmo, uatom].into(),
+            &[coin(1_000, "uosmo"), coin(1_000, "uatom"), coin(1, "uion")],
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::UndeclaredFunds { denom, amount }
+                if denom == "uion" && amount == Uint128::one()
+        ));
+    }
+
+    #[test]
+    fn test_calc_swap_min_out() {
+        let offer_asset = Asset::native("uatom", 1_000_000u128);
+        let return_asset = Asset::native("uosmo", 1_980_000u128);
+        let spot_return = Uint128::from(2_000_000u128);
+
+        // No protection requested
+        let min_out =
+            calc_swap_min_out(&offer_asset, &return_asset, spot_return, None, None).unwrap();
+        assert_eq!(min_out, Uint128::one());
+
+        // Max spread relative to the return amount at the spot price
+        let min_out = calc_swap_min_out(
+            &offer_asset,
+            &return_asset,
+            spot_return,
+            Some(Decimal::percent(2)),
+            None,
+        )
+        .unwrap();
+        assert_eq!(min_out, Uint128::from(1_960_000u128));
+
+        // The price impact of the swap is larger than the max spread
+        let err = calc_swap_min_out(
+            &offer_asset,
+            &return_asset,
+            spot_return,
+            Some(Decimal::permille(5)),
+            None,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::MaxSpreadAssertion {
+                min_return,
+                return_amount
+            } if min_return == Uint256::from(1_990_000u128)
+                && return_amount == Uint128::from(1_980_000u128)
+        ));
+
+        // Belief price with the default max spread
+        let min_out = calc_swap_min_out(
+            &offer_asset,
+            &return_asset,
+            spot_return,
+            None,
+            Some(Decimal::percent(51)),
+        )
+        .unwrap();
+        assert_eq!(min_out, Uint128::from(1_950_980u128));
+
+        // Pool price moved away from the belief price
+        let err = calc_swap_min_out(
+            &offer_asset,
+            &return_asset,
+            spot_return,
+            Some(Decimal::permille(1)),
+            Some(Decimal::percent(50)),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::MaxSpreadAssertion {
+                min_return,
+                return_amount
+            } if min_return == Uint256::from(1_998_000u128)
+                && return_amount == Uint128::from(1_980_000u128)
+        ));
+
+        // An expected return that does not fit in 128 bits fails instead of
+        // overflowing
+        let err = calc_swap_min_out(
+            &Asset::native("uatom", u128::MAX),
+            &return_asset,
+            spot_return,
+            None,
+            Some(Decimal::percent(1)),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::MaxSpreadAssertion { min_return, .. }
+                if min_return > Uint256::from(u128::MAX)
+        ));
+
+        // Zero belief price
+        let err = calc_swap_min_out(
+            &offer_asset,
+            &return_asset,
+            spot_return,
+            None,
+            Some(Decimal::zero()),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::ZeroBeliefPrice {}));
+    }
+
+    #[test]
+    fn test_balancing_swap_max_spread() {
+        let strategy = XykStrategy {
+            fee: Decimal::permille(3),
+            maker_fee: Decimal::zero(),
+        };
+        let assets: AssetList = vec![Asset::native("uatom", 1_000_000u128)].into();
+
+        // A small deposit barely moves the price of a deep pool
+        let plan = plan_balancing_provide(
+            &strategy,
+            vec![
+                Asset::native("uatom", 1_000_000_000_000u128),
+                Asset::native("uosmo", 1_000_000_000_000u128),
+            ],
+            assets.clone(),
+        )
+        .unwrap();
+        let (offer_asset, return_asset) = plan.swap.unwrap();
+        calc_swap_min_out(
+            &offer_asset,
+            &return_asset,
+            plan.spot_return,
+            Some(Decimal::percent(1)),
+            None,
+        )
+        .unwrap();
+
+        // The same deposit moves the price of a shallow pool by more than the
+        // max spread
+        let plan = plan_balancing_provide(
+            &strategy,
+            vec![
+                Asset::native("uatom", 1_000_000u128),
+                Asset::native("uosmo", 1_000_000u128),
+            ],
+            assets,
+        )
+        .unwrap();
+        let (offer_asset, return_asset) = plan.swap.unwrap();
+        let err = calc_swap_min_out(
+            &offer_asset,
+            &return_asset,
+            plan.spot_return,
+            Some(Decimal::percent(1)),
+            None,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::MaxSpreadAssertion { return_amount, .. }
+                if return_amount == return_asset.amount
+        ));
+    }
+
+    #[test]
+    fn test_apply_slippage_tolerance() {
+        let expected_lp = Uint128::from(1_000_000u128);
+
+        let min_out =
+            apply_slippage_tolerance(Uint128::zero(), expected_lp, Decimal::percent(1)).unwrap();
+        assert_eq!(min_out, Uint128::from(990_000u128));
+
+        // An absolute min_out higher than the tolerance allows takes precedence
+        let min_out =
+            apply_slippage_tolerance(Uint128::from(995_000u128), expected_lp, Decimal::percent(1))
+                .unwrap();
+        assert_eq!(min_out, Uint128::from(995_000u128));
+
+        // Tolerance above 100%
+        apply_slippage_tolerance(Uint128::zero(), expected_lp, Decimal::percent(101)).unwrap_err();
+    }
+
+    #[test]
+    fn test_check_weights() {
+        let deps = mock_dependencies();
+        let weight = |denom: &str, weight: u64| AssetWeight {
+            info: AssetInfoUnchecked::native(denom),
+            weight: Decimal::percent(weight),
+        };
+        let uosmo = AssetInfo::native("uosmo");
+        let uatom = AssetInfo::native("uatom");
+
+        let weights = check_weights(
+            deps.as_ref(),
+            &[uosmo.clone(), uatom.clone()],
+            vec![weight("uatom", 25), weight("uosmo", 75)],
+        )
+        .unwrap();
+        assert_eq!(weights, [Decimal::percent(75), Decimal::percent(25)]);
+
+        let err = check_weights(
+            deps.as_ref(),
+            &[uosmo.clone(), uatom.clone()],
+            vec![weight("uatom", 25), weight("uosmo", 50)],
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::InvalidWeights {}));
+
+        // A weight for a third pool asset must not index past the weights
+        let err = check_weights(
+            deps.as_ref(),
+            &[uosmo, uatom, AssetInfo::native("udai")],
+            vec![weight("udai", 100)],
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::MoreThanTwoAssets {}));
+    }
+
+    #[test]
+    fn test_migrate_from_v0_1() {
+        let mut deps = mock_dependencies();
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.1.0").unwrap();
+        Item::<Addr>::new("astroport_factory")
+            .save(deps.as_mut().storage, &Addr::unchecked("factory"))
+            .unwrap();
+
+        // An owner is required when migrating from v0.1
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg { owner: None }).unwrap_err();
+        assert!(matches!(err, ContractError::MissingOwner { .. }));
+
+        let msg = MigrateMsg {
+            owner: Some("owner".to_string()),
+        };
+        migrate(deps.as_mut(), mock_env(), msg).unwrap();
+
+        let config = CONFIG.load(deps.as_ref().storage).unwrap();
+        assert_eq!(config.astroport_factory, Addr::unchecked("factory"));
+        let owner = OWNER.load(deps.as_ref().storage).unwrap();
+        assert_eq!(owner, Addr::unchecked("owner"));
+        let version = get_contract_version(deps.as_ref().storage).unwrap();
+        assert_eq!(version.version, CONTRACT_VERSION);
+    }
+
+    #[test]
+    fn test_migrate_rejects_downgrade_and_other_contracts() {
+        let mut deps = mock_dependencies();
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "99.0.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg { owner: None }).unwrap_err();
+        assert!(matches!(err, ContractError::MigrationDowngrade { .. }));
+
+        set_contract_version(deps.as_mut().storage, "crates.io:other-contract", "0.1.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg { owner: None }).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidContractName { .. }));
+    }
+
+    #[test]
+    fn test_ownership_and_update_config() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            owner: "owner".to_string(),
+            astroport_factory: "factory".to_string(),
+            guardian: None,
+            astroport_generator: None,
+            astroport_router: None,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        // Only the owner can update the config
+        let msg = ExecuteMsg::UpdateConfig {
+            astroport_factory: Some("new_factory".to_string()),
+            guardian: None,
+            astroport_generator: None,
+            astroport_router: None,
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        let config = CONFIG.load(deps.as_ref().storage).unwrap();
+        assert_eq!(config.astroport_factory, Addr::unchecked("new_factory"));
+
+        // Optional addresses can be set and cleared again, while the ones not
+        // given are left unchanged
+        let msg = ExecuteMsg::UpdateConfig {
+            astroport_factory: None,
+            guardian: Some(AddrUpdate::Set("guardian".to_string())),
+            astroport_generator: Some(AddrUpdate::Set("generator".to_string())),
+            astroport_router: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        let msg = ExecuteMsg::UpdateConfig {
+            astroport_factory: None,
+            guardian: Some(AddrUpdate::Clear {}),
+            astroport_generator: None,
+            astroport_router: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        let config = CONFIG.load(deps.as_ref().storage).unwrap();
+        assert_eq!(config.astroport_factory, Addr::unchecked("new_factory"));
+        assert_eq!(config.guardian, None);
+        assert_eq!(
+            config.astroport_generator,
+            Some(Addr::unchecked("generator"))
+        );
+        assert_eq!(config.astroport_router, None);
+
+        // Claiming without a proposal fails
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("new_owner", &[]),
+            ExecuteMsg::ClaimOwnership {},
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::NoOwnershipProposal {}));
+
+        // Propose and claim the ownership
+        let msg = ExecuteMsg::ProposeNewOwner {
+            owner: "new_owner".to_string(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            ExecuteMsg::ClaimOwnership {},
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("new_owner", &[]),
+            ExecuteMsg::ClaimOwnership {},
+        )
+        .unwrap();
+
+        let owner = OWNER.load(deps.as_ref().storage).unwrap();
+        assert_eq!(owner, Addr::unchecked("new_owner"));
+        let proposed_owner = PROPOSED_OWNER.may_load(deps.as_ref().storage).unwrap();
+        assert_eq!(proposed_owner, None);
+    }
+
+    #[test]
+    fn test_pause() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            owner: "owner".to_string(),
+            astroport_factory: "factory".to_string(),
+            guardian: Some("guardian".to_string()),
+            astroport_generator: None,
+            astroport_router: None,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let provide_msg = ExecuteMsg::BalancingProvideLiquidity {
+            assets: vec![].into(),
+            min_out: Uint128::zero(),
+            pool: to_binary(&AstroportPool {
+                pair_addr: Addr::unchecked("pair"),
+                lp_token_addr: Addr::unchecked("lp_token"),
+                pair_type: PairType::Xyk {},
+            })
+            .unwrap(),
+            recipient: None,
+            max_spread: None,
+            belief_price: None,
+            slippage_tolerance: None,
+            auto_stake: None,
+            swap_routes: None,
+        };
+
+        // Only the owner and guardian can pause
+        let msg = ExecuteMsg::SetPoolPaused {
+            pair_addr: "pair".to_string(),
+            paused: true,
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("user", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), mock_env(), mock_info("guardian", &[]), msg).unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("user", &[]),
+            provide_msg.clone(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::PoolPaused { .. }));
+
+        // The guardian can not unpause
+        let msg = ExecuteMsg::SetPoolPaused {
+            pair_addr: "pair".to_string(),
+            paused: false,
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("guardian", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+        // Pause the whole contract
+        let msg = ExecuteMsg::SetPaused { paused: true };
+        execute(deps.as_mut(), mock_env(), mock_info("guardian", &[]), msg).unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("user", &[]),
+            provide_msg,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Paused {}));
+    }
+
+    #[test]
+    fn test_receive_only_provides_sent_cw20() {
+        let mut deps = mock_dependencies();
+        let hook_msg = |assets: Vec<Asset>| Cw20HookMsg::BalancingProvideLiquidity {
+            assets: assets.into(),
+            min_out: Uint128::zero(),
+            pool: to_binary(&AstroportPool {
+                pair_addr: Addr::unchecked("pair"),
+                lp_token_addr: Addr::unchecked("lp_token"),
+                pair_type: PairType::Xyk {},
+            })
+            .unwrap(),
+            recipient: None,
+            max_spread: None,
+            belief_price: None,
+            slippage_tolerance: None,
+            auto_stake: None,
+            swap_routes: None,
+        };
+        let receive_msg = |assets: Vec<Asset>| {
+            ExecuteMsg::Receive(Cw20ReceiveMsg {
+                sender: "user".to_string(),
+                amount: Uint128::from(1_000u128),
+                msg: to_binary(&hook_msg(assets)).unwrap(),
+            })
+        };
+
+        // Other cw20s can not be pulled from the sender named in the message
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("token", &[]),
+            receive_msg(vec![Asset::cw20(Addr::unchecked("other"), 1_000u128)]),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::UnexpectedHookAsset { .. }));
+
+        // Native tokens can not be sent along with a cw20
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("token", &[]),
+            receive_msg(vec![Asset::native("uosmo", 1_000u128)]),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::UnexpectedHookAsset { .. }));
+    }
+
+    #[test]
+    fn test_compound_provides_and_stakes() {
+        let (mut deps, pool) = setup_xyk_pool();
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, vec![coin(1_000_000, "uatom")]);
+        let msg = ExecuteMsg::Compound {
+            pool: to_binary(&pool).unwrap(),
+            rewards: vec![Asset::native("uatom", 1_000_000u128)].into(),
+            min_out: Uint128::one(),
+            max_spread: Decimal::percent(1),
+            swap_routes: None,
+        };
+        let info = mock_info("user", &[coin(1_000_000, "uatom")]);
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap();
+
+        // The rewards are balanced and provided, but nothing is claimed
+        let contracts = executed_contracts(&res);
+        assert!(contracts.contains(&"pair".to_string()));
+        assert!(!contracts.contains(&"generator".to_string()));
+
+        // The LP tokens received are staked for the caller
+        let callback = callbacks(&res).pop().unwrap();
+        assert!(matches!(
+            &callback,
+            CallbackMsg::ReturnLpTokens {
+                recipient,
+                auto_stake: true,
+                ..
+            } if recipient.as_str() == "user"
+        ));
+        mock_astroport(&mut deps.querier, 1_000);
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(MOCK_CONTRACT_ADDR, &[]),
+            ExecuteMsg::Callback(callback),
+        )
+        .unwrap();
+        assert_eq!(executed_contracts(&res), vec!["lp_token".to_string()]);
+        let send_msg: Cw20ExecuteMsg = match &res.messages[0].msg {
+            CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => from_binary(msg).unwrap(),
+            msg => panic!("unexpected message {:?}", msg),
+        };
+        assert!(matches!(
+            send_msg,
+            Cw20ExecuteMsg::Send { contract, amount, .. }
+                if contract == "generator" && amount == Uint128::from(1_000u128)
+        ));
+
+        // There is nothing to stake in without a generator
+        let mut config = CONFIG.load(deps.as_ref().storage).unwrap();
+        config.astroport_generator = None;
+        CONFIG.save(deps.as_mut().storage, &config).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::GeneratorNotSet {}));
+    }
+
+    #[test]
+    fn test_exit_position_uses_lp_tokens_of_caller() {
+        let (mut deps, pool) = setup_xyk_pool();
+        let msg = ExecuteMsg::ExitPosition {
+            pool: to_binary(&pool).unwrap(),
+            amount: Uint128::from(1_000u128),
+            ask_asset: AssetInfoUnchecked::native("uatom"),
+            min_out: Uint128::one(),
+            recipient: None,
+            swap_routes: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("user", &[]), msg).unwrap();
+
+        // The LP tokens are pulled from the caller, who unstaked them before,
+        // and nothing is withdrawn from the generator
+        let transfer_msg: Cw20ExecuteMsg = match &res.messages[0].msg {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr, msg, ..
+            }) if contract_addr == "lp_token" => from_binary(msg).unwrap(),
+            msg => panic!("unexpected message {:?}", msg),
+        };
+        assert!(matches!(
+            transfer_msg,
+            Cw20ExecuteMsg::TransferFrom { owner, amount, .. }
+                if owner == "user" && amount == Uint128::from(1_000u128)
+        ));
+        assert!(!executed_contracts(&res).contains(&"generator".to_string()));
+
+        // The withdrawn assets are swapped into the ask asset for the caller
+        assert!(matches!(
+            callbacks(&res).pop().unwrap(),
+            CallbackMsg::SwapWithdrawnAssets {
+                ask_asset_info,
+                recipient,
+                ..
+            } if ask_asset_info == AssetInfo::native("uatom") && recipient.as_str() == "user"
+        ));
+    }
+
+    #[test]
+    fn test_balancing_withdraw_liquidity() {
+        let (mut deps, pool) = setup_xyk_pool();
+        let withdraw_msg = |lp_token: Asset| ExecuteMsg::BalancingWithdrawLiquidity {
+            lp_token: lp_token.into(),
+            ask_asset: AssetInfoUnchecked::native("uatom"),
+            min_out: Uint128::from(900u128),
+            pool: to_binary(&pool).unwrap(),
+            recipient: None,
+            swap_routes: None,
+        };
+
+        // LP tokens of another pool are rejected
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("user", &[]),
+            withdraw_msg(Asset::cw20(Addr::unchecked("other_lp_token"), 1_000u128)),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::InvalidLpToken { expected, actual }
+                if expected.contains("lp_token") && actual.contains("other_lp_token")
+        ));
+
+        // The LP tokens are pulled from the caller and withdrawn in the pool
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("user", &[]),
+            withdraw_msg(Asset::cw20(Addr::unchecked("lp_token"), 1_000u128)),
+        )
+        .unwrap();
+        assert_eq!(
+            executed_contracts(&res),
+            vec!["lp_token", "lp_token", MOCK_CONTRACT_ADDR]
+        );
+        let callback = callbacks(&res).pop().unwrap();
+
+        // Once the pool assets are withdrawn, the ones that are not the ask
+        // asset are swapped into it in the pool
+        deps.querier.update_balance(
+            MOCK_CONTRACT_ADDR,
+            vec![coin(500, "uatom"), coin(500, "uosmo")],
+        );
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(MOCK_CONTRACT_ADDR, &[]),
+            ExecuteMsg::Callback(callback),
+        )
+        .unwrap();
+        let swap_funds = res.messages.iter().find_map(|sub_msg| match &sub_msg.msg {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr,
+                funds,
+                ..
+            }) if contract_addr == "pair" => Some(funds.clone()),
+            _ => None,
+        });
+        assert_eq!(swap_funds, Some(vec![coin(500, "uosmo")]));
+
+        // Everything received of the ask asset is returned to the caller,
+        // provided it is at least `min_out`
+        assert!(matches!(
+            callbacks(&res).pop().unwrap(),
+            CallbackMsg::ReturnAsset {
+                asset_info,
+                balance_before,
+                min_out,
+                recipient,
+            } if asset_info == AssetInfo::native("uatom")
+                && balance_before.is_zero()
+                && min_out == Uint128::from(900u128)
+                && recipient.as_str() == "user"
+        ));
+    }
+
+    #[test]
+    fn test_simulate_withdraw_liquidity_to_ratio_from_empty_pool() {
+        let (mut deps, pool) = setup_xyk_pool();
+        let weights = vec![AssetWeight {
+            info: AssetInfoUnchecked::native("uatom"),
+            weight: Decimal::one(),
+        }];
+        let lp_token = Asset::cw20(Addr::unchecked("lp_token"), 1_000u128);
+
+        query_simulate_withdraw_liquidity_to_ratio(
+            deps.as_ref(),
+            lp_token.clone(),
+            weights.clone(),
+            pool.clone(),
+        )
+        .unwrap();
+
+        // Without any LP tokens issued there is no share of the reserves to
+        // withdraw
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { contract_addr, .. } if contract_addr == "pair" => {
+                SystemResult::Ok(ContractResult::Ok(Binary::from(
+                    br#"{
+                        "assets": [
+                            {
+                                "info": { "native_token": { "denom": "uatom" } },
+                                "amount": "0"
+                            },
+                            {
+                                "info": { "native_token": { "denom": "uosmo" } },
+                                "amount": "0"
+                            }
+                        ],
+                        "total_share": "0"
+                    }"#
+                    .as_ref(),
+                )))
+            }
+            _ => SystemResult::Err(SystemError::UnsupportedRequest {
+                kind: format!("{:?}", query),
+            }),
+        });
+        let err =
+            query_simulate_withdraw_liquidity_to_ratio(deps.as_ref(), lp_token, weights, pool)
+                .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::PoolEmpty { pair_addr } if pair_addr == "pair"
+        ));
+    }
+
+    #[test]
+    fn test_balancing_provide_passes_pool_to_callback() {
+        let (mut deps, pool) = setup_xyk_pool();
+        let strategy = XykStrategy {
+            fee: Decimal::permille(3),
+            maker_fee: Decimal::zero(),
+        };
+
+        // A single sided deposit is partly swapped in the pool, and the rest
+        // is provided in a callback
+        let (res, provide_assets, min_out) = balancing_provide(
+            deps.as_ref(),
+            &mock_env(),
+            &pool,
+            &strategy,
+            vec![Asset::native("uatom", 1_000_000u128)].into(),
+            Uint128::one(),
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(min_out, Uint128::one());
+        assert_eq!(executed_contracts(&res), vec!["pair", MOCK_CONTRACT_ADDR]);
+
+        // The callback gets the pool back from its serialized form, and
+        // provides the planned assets to it
+        let callback = callbacks(&res).pop().unwrap();
+        match &callback {
+            CallbackMsg::ProvideLiquidity {
+                assets,
+                pool: callback_pool,
+                ..
+            } => {
+                assert_eq!(assets, &provide_assets);
+                assert_eq!(from_binary::<AstroportPool>(callback_pool).unwrap(), pool);
+            }
+            callback => panic!("unexpected callback {:?}", callback),
+        }
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(MOCK_CONTRACT_ADDR, &[]),
+            ExecuteMsg::Callback(callback),
+        )
+        .unwrap();
+        assert_eq!(executed_contracts(&res), vec!["pair"]);
+
+        // With a slippage tolerance, the minimum LP tokens to receive are
+        // simulated from the reserves after the swap
+        let (_, _, min_out) = balancing_provide(
+            deps.as_ref(),
+            &mock_env(),
+            &pool,
+            &strategy,
+            vec![Asset::native("uatom", 1_000_000u128)].into(),
+            Uint128::one(),
+            None,
+            None,
+            Some(Decimal::percent(1)),
+        )
+        .unwrap();
+        assert!(min_out > Uint128::from(400_000u128));
+    }
+}
@@ -1,4 +1,4 @@
-use cosmwasm_std::{OverflowError, StdError};
+use cosmwasm_std::{OverflowError, StdError, Uint128, Uint256};
 use cw_bigint::TryFromBigIntError;
 use cw_dex::CwDexError;
 use thiserror::Error;
@@ -17,14 +17,97 @@ pub enum ContractError {
     #[error("{0}")]
     Overflow(#[from] OverflowError),
 
+    #[error("{0}")]
+    Semver(#[from] semver::Error),
+
     #[error("Unauthorized")]
     Unauthorized {},
 
-    #[error("Can't provide liquidity with more than 2 assets")]
+    #[error("Contract is paused")]
+    Paused {},
+
+    #[error("Pool with pair address {pair_addr} is paused")]
+    PoolPaused { pair_addr: String },
+
+    #[error("Astroport generator is not set")]
+    GeneratorNotSet {},
+
+    #[error("Astroport router is not set")]
+    RouterNotSet {},
+
+    #[error("Invalid swap route for {offer_asset}")]
+    InvalidSwapRoute { offer_asset: String },
+
+    #[error("No Astroport pair found to swap {offer_asset} into the pool assets")]
+    NoSwapRoute { offer_asset: String },
+
+    #[error("No ownership proposal found")]
+    NoOwnershipProposal {},
+
+    #[error("An owner must be given when migrating from version {stored_version}")]
+    MissingOwner { stored_version: String },
+
+    #[error("Cannot migrate from contract {contract_name}")]
+    InvalidContractName { contract_name: String },
+
+    #[error("Cannot migrate from version {stored_version} to older version {new_version}")]
+    MigrationDowngrade {
+        stored_version: String,
+        new_version: String,
+    },
+
+    #[error("Can't provide liquidity with more than 2 assets to non-stable pools")]
     MoreThanTwoAssets {},
 
-    #[error("Custom pair type not supported")]
-    CustomPairType {},
+    #[error("Pair type {pair_type} not supported")]
+    UnsupportedPairType { pair_type: String },
+
+    #[error("Weights must add up to one")]
+    InvalidWeights {},
+
+    #[error("Pool with pair address {pair_addr} does not match the pair registered in the Astroport factory")]
+    PoolNotRegistered { pair_addr: String },
+
+    #[error(
+        "Balancing swap would return {return_amount}, which is less than the minimum {min_return} allowed by max_spread"
+    )]
+    MaxSpreadAssertion {
+        min_return: Uint256,
+        return_amount: Uint128,
+    },
+
+    #[error("Belief price can not be zero")]
+    ZeroBeliefPrice {},
+
+    #[error("Asset {asset} is not in the pool")]
+    AssetNotInPool { asset: String },
+
+    #[error("Asset {asset} is listed more than once")]
+    DuplicateAsset { asset: String },
+
+    #[error("Exactly two assets with non-zero amounts are needed to create a pool")]
+    InvalidBootstrapAssets {},
+
+    #[error("Pool with pair address {pair_addr} already has liquidity")]
+    PoolNotEmpty { pair_addr: String },
+
+    #[error("Pool with pair address {pair_addr} has no liquidity")]
+    PoolEmpty { pair_addr: String },
+
+    #[error("No assets with a non-zero amount were provided")]
+    NoAssetsProvided {},
+
+    #[error("Only the sent cw20 can be provided through a cw20 hook, got {asset}")]
+    UnexpectedHookAsset { asset: String },
+
+    #[error("Received {amount}{denom} that is not declared in assets")]
+    UndeclaredFunds { denom: String, amount: Uint128 },
+
+    #[error("Invalid LP token. Expected: {expected}, got: {actual}")]
+    InvalidLpToken { expected: String, actual: String },
+
+    #[error("Received {received}, which is less than min_out {min_out}")]
+    MinOutNotReceived { min_out: Uint128, received: Uint128 },
 }
 
 impl From<ContractError> for StdError {
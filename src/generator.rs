@@ -0,0 +1,89 @@
+//! Module containing helpers for staking LP tokens in the Astroport generator
+
+use astroport::generator::{
+    Cw20HookMsg as GeneratorCw20HookMsg, ExecuteMsg as GeneratorExecuteMsg,
+    QueryMsg as GeneratorQueryMsg,
+};
+use cosmwasm_std::{to_binary, Addr, Coin, CosmosMsg, QuerierWrapper, StdResult, Uint128, WasmMsg};
+use cw20::Cw20ExecuteMsg;
+use cw_asset::{Asset, AssetInfo};
+
+/// Returns the denom or contract address of an LP token, which is how the
+/// generator identifies it.
+pub fn lp_token_key(info: &AssetInfo) -> String {
+    match info {
+        AssetInfo::Native(denom) => denom.clone(),
+        AssetInfo::Cw20(contract_addr) => contract_addr.to_string(),
+    }
+}
+
+/// Returns a message depositing `lp_token` into the generator on behalf of
+/// `recipient`, who then owns the deposit and receives its rewards. Cw20 LP
+/// tokens are sent with a deposit hook, while native LP tokens are sent as
+/// funds.
+pub fn deposit_msg(generator: &Addr, lp_token: &Asset, recipient: &Addr) -> StdResult<CosmosMsg> {
+    let msg = match &lp_token.info {
+        AssetInfo::Cw20(contract_addr) => WasmMsg::Execute {
+            contract_addr: contract_addr.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: generator.to_string(),
+                amount: lp_token.amount,
+                msg: to_binary(&GeneratorCw20HookMsg::DepositFor(recipient.clone().into()))?,
+            })?,
+            funds: vec![],
+        },
+        AssetInfo::Native(denom) => WasmMsg::Execute {
+            contract_addr: generator.to_string(),
+            msg: to_binary(&GeneratorExecuteMsg::Deposit {
+                recipient: Some(recipient.to_string()),
+            })?,
+            funds: vec![Coin {
+                denom: denom.clone(),
+                amount: lp_token.amount,
+            }],
+        },
+    };
+    Ok(msg.into())
+}
+
+/// Queries the amount of `lp_token` that `user` has deposited in the generator
+pub fn query_deposit(
+    querier: &QuerierWrapper,
+    generator: &Addr,
+    lp_token: &AssetInfo,
+    user: &Addr,
+) -> StdResult<Uint128> {
+    querier.query_wasm_smart(
+        generator.to_string(),
+        &GeneratorQueryMsg::Deposit {
+            lp_token: lp_token_key(lp_token),
+            user: user.to_string(),
+        },
+    )
+}
+
+/// Returns a message withdrawing `lp_token` from the generator
+pub fn withdraw_msg(generator: &Addr, lp_token: &Asset) -> StdResult<CosmosMsg> {
+    Ok(WasmMsg::Execute {
+        contract_addr: generator.to_string(),
+        msg: to_binary(&GeneratorExecuteMsg::Withdraw {
+            lp_token: lp_token_key(&lp_token.info),
+            amount: lp_token.amount,
+        })?,
+        funds: vec![],
+    }
+    .into())
+}
+
+/// Returns a message claiming the pending generator rewards of the sender for
+/// `lp_token`
+pub fn claim_rewards_msg(generator: &Addr, lp_token: &AssetInfo) -> StdResult<CosmosMsg> {
+    Ok(WasmMsg::Execute {
+        contract_addr: generator.to_string(),
+        msg: to_binary(&GeneratorExecuteMsg::ClaimRewards {
+            lp_tokens: vec![lp_token_key(lp_token)],
+        })?,
+        funds: vec![],
+    }
+    .into())
+}
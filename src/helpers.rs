@@ -1,12 +1,19 @@
 use apollo_utils::assets::separate_natives_and_cw20s;
+use astroport::factory::PairType;
 use cw20::Cw20ExecuteMsg;
-use cw_asset::AssetList;
+use cw_asset::{Asset, AssetInfo, AssetList};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{to_binary, Addr, Api, Binary, Coin, CosmosMsg, StdResult, Uint128, WasmMsg};
+use cosmwasm_std::{
+    from_binary, to_binary, Addr, Api, Binary, Coin, CosmosMsg, Decimal, StdError, StdResult,
+    Uint128, WasmMsg,
+};
+use cw_dex::astroport::AstroportPool;
+use cw_dex::traits::Pool;
 
-use crate::msg::ExecuteMsg;
+use crate::generator::{claim_rewards_msg, withdraw_msg};
+use crate::msg::{AssetWeight, Cw20HookMsg, ExecuteMsg, PairIdentifier, SwapRoute};
 
 /// LiquidityHelper is a wrapper around Addr that provides a lot of helpers
 /// for working with this contract. It can be imported by other contracts
@@ -36,17 +43,17 @@ impl LiquidityHelper {
         .into())
     }
 
-    pub fn balancing_provide_liquidity(
+    /// Separates the native tokens in `assets`, which should be sent as funds,
+    /// and returns messages increasing the allowance of this contract for all
+    /// cw20s.
+    fn prepare_funds_and_allowances(
         &self,
-        assets: AssetList,
-        min_out: Uint128,
-        pool: Binary,
-        recipient: Option<String>,
-    ) -> StdResult<Vec<CosmosMsg>> {
-        let (funds, cw20s) = separate_natives_and_cw20s(&assets);
+        assets: &AssetList,
+    ) -> StdResult<(Vec<Coin>, Vec<CosmosMsg>)> {
+        let (funds, cw20s) = separate_natives_and_cw20s(assets);
 
         // Increase allowance for all cw20s
-        let mut msgs: Vec<CosmosMsg> = cw20s
+        let msgs: Vec<CosmosMsg> = cw20s
             .into_iter()
             .map(|asset| {
                 Ok(CosmosMsg::Wasm(WasmMsg::Execute {
@@ -61,12 +68,257 @@ impl LiquidityHelper {
             })
             .collect::<StdResult<Vec<_>>>()?;
 
+        Ok((funds, msgs))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn balancing_provide_liquidity(
+        &self,
+        assets: AssetList,
+        min_out: Uint128,
+        pool: Binary,
+        recipient: Option<String>,
+        max_spread: Option<Decimal>,
+        belief_price: Option<Decimal>,
+        slippage_tolerance: Option<Decimal>,
+        auto_stake: Option<bool>,
+        swap_routes: Option<Vec<SwapRoute>>,
+    ) -> StdResult<Vec<CosmosMsg>> {
+        let (funds, mut msgs) = self.prepare_funds_and_allowances(&assets)?;
+
         msgs.push(self.call(
             ExecuteMsg::BalancingProvideLiquidity {
                 assets: assets.into(),
                 min_out,
                 pool,
                 recipient,
+                max_spread,
+                belief_price,
+                slippage_tolerance,
+                auto_stake,
+                swap_routes,
+            },
+            funds,
+        )?);
+
+        Ok(msgs)
+    }
+
+    /// Same as `balancing_provide_liquidity`, but sends `cw20` to the contract
+    /// with `Cw20ExecuteMsg::Send` instead of increasing the allowance for it.
+    /// Only the sent cw20 can be provided this way, since no other assets can
+    /// be sent along with it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn send_and_balancing_provide_liquidity(
+        &self,
+        cw20: Asset,
+        min_out: Uint128,
+        pool: Binary,
+        recipient: Option<String>,
+        max_spread: Option<Decimal>,
+        belief_price: Option<Decimal>,
+        slippage_tolerance: Option<Decimal>,
+        auto_stake: Option<bool>,
+        swap_routes: Option<Vec<SwapRoute>>,
+    ) -> StdResult<CosmosMsg> {
+        let contract_addr = match &cw20.info {
+            AssetInfo::Cw20(contract_addr) => contract_addr.to_string(),
+            AssetInfo::Native(_) => {
+                return Err(StdError::generic_err("Only cw20s can be sent"));
+            }
+        };
+        let hook_msg = Cw20HookMsg::BalancingProvideLiquidity {
+            assets: AssetList::new().into(),
+            min_out,
+            pool,
+            recipient,
+            max_spread,
+            belief_price,
+            slippage_tolerance,
+            auto_stake,
+            swap_routes,
+        };
+        Ok(WasmMsg::Execute {
+            contract_addr,
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: self.addr().into(),
+                amount: cw20.amount,
+                msg: to_binary(&hook_msg)?,
+            })?,
+            funds: vec![],
+        }
+        .into())
+    }
+
+    /// Same as `balancing_provide_liquidity`, but lets the contract look up
+    /// the pool in the Astroport factory from the given `PairIdentifier`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn balancing_provide_liquidity_by_pair(
+        &self,
+        assets: AssetList,
+        min_out: Uint128,
+        pair: PairIdentifier,
+        recipient: Option<String>,
+        max_spread: Option<Decimal>,
+        belief_price: Option<Decimal>,
+        slippage_tolerance: Option<Decimal>,
+        auto_stake: Option<bool>,
+        swap_routes: Option<Vec<SwapRoute>>,
+    ) -> StdResult<Vec<CosmosMsg>> {
+        let (funds, mut msgs) = self.prepare_funds_and_allowances(&assets)?;
+
+        msgs.push(self.call(
+            ExecuteMsg::BalancingProvideLiquidityByPair {
+                assets: assets.into(),
+                min_out,
+                pair,
+                recipient,
+                max_spread,
+                belief_price,
+                slippage_tolerance,
+                auto_stake,
+                swap_routes,
+            },
+            funds,
+        )?);
+
+        Ok(msgs)
+    }
+
+    pub fn create_pair_and_provide_liquidity(
+        &self,
+        assets: AssetList,
+        pair_type: PairType,
+        init_params: Option<Binary>,
+        min_out: Uint128,
+        recipient: Option<String>,
+    ) -> StdResult<Vec<CosmosMsg>> {
+        let (funds, mut msgs) = self.prepare_funds_and_allowances(&assets)?;
+
+        msgs.push(self.call(
+            ExecuteMsg::CreatePairAndProvideLiquidity {
+                assets: assets.into(),
+                pair_type,
+                init_params,
+                min_out,
+                recipient,
+            },
+            funds,
+        )?);
+
+        Ok(msgs)
+    }
+
+    pub fn balancing_withdraw_liquidity(
+        &self,
+        lp_token: Asset,
+        ask_asset: AssetInfo,
+        min_out: Uint128,
+        pool: Binary,
+        recipient: Option<String>,
+        swap_routes: Option<Vec<SwapRoute>>,
+    ) -> StdResult<Vec<CosmosMsg>> {
+        let (funds, mut msgs) =
+            self.prepare_funds_and_allowances(&vec![lp_token.clone()].into())?;
+
+        msgs.push(self.call(
+            ExecuteMsg::BalancingWithdrawLiquidity {
+                lp_token: lp_token.into(),
+                ask_asset: ask_asset.into(),
+                min_out,
+                pool,
+                recipient,
+                swap_routes,
+            },
+            funds,
+        )?);
+
+        Ok(msgs)
+    }
+
+    pub fn balancing_withdraw_liquidity_to_ratio(
+        &self,
+        lp_token: Asset,
+        weights: Vec<AssetWeight>,
+        min_out: AssetList,
+        pool: Binary,
+        recipient: Option<String>,
+    ) -> StdResult<Vec<CosmosMsg>> {
+        let (funds, mut msgs) =
+            self.prepare_funds_and_allowances(&vec![lp_token.clone()].into())?;
+
+        msgs.push(self.call(
+            ExecuteMsg::BalancingWithdrawLiquidityToRatio {
+                lp_token: lp_token.into(),
+                weights,
+                min_out: min_out.into(),
+                pool,
+                recipient,
+            },
+            funds,
+        )?);
+
+        Ok(msgs)
+    }
+
+    /// Returns messages unstaking `amount` of the caller's LP tokens of `pool`
+    /// from the generator and exiting the position with them.
+    #[allow(clippy::too_many_arguments)]
+    pub fn exit_position(
+        &self,
+        generator: &Addr,
+        pool: Binary,
+        amount: Uint128,
+        ask_asset: AssetInfo,
+        min_out: Uint128,
+        recipient: Option<String>,
+        swap_routes: Option<Vec<SwapRoute>>,
+    ) -> StdResult<Vec<CosmosMsg>> {
+        let lp_token = Asset::new(from_binary::<AstroportPool>(&pool)?.lp_token(), amount);
+        let (funds, allowance_msgs) =
+            self.prepare_funds_and_allowances(&vec![lp_token.clone()].into())?;
+
+        let mut msgs = vec![withdraw_msg(generator, &lp_token)?];
+        msgs.extend(allowance_msgs);
+        msgs.push(self.call(
+            ExecuteMsg::ExitPosition {
+                pool,
+                amount,
+                ask_asset: ask_asset.into(),
+                min_out,
+                recipient,
+                swap_routes,
+            },
+            funds,
+        )?);
+
+        Ok(msgs)
+    }
+
+    /// Returns messages claiming the caller's generator rewards for the LP
+    /// tokens of `pool` and compounding `rewards`, which must not be more
+    /// than the rewards claimed.
+    pub fn compound(
+        &self,
+        generator: &Addr,
+        pool: Binary,
+        rewards: AssetList,
+        min_out: Uint128,
+        max_spread: Decimal,
+        swap_routes: Option<Vec<SwapRoute>>,
+    ) -> StdResult<Vec<CosmosMsg>> {
+        let lp_token = from_binary::<AstroportPool>(&pool)?.lp_token();
+        let (funds, allowance_msgs) = self.prepare_funds_and_allowances(&rewards)?;
+
+        let mut msgs = vec![claim_rewards_msg(generator, &lp_token)?];
+        msgs.extend(allowance_msgs);
+        msgs.push(self.call(
+            ExecuteMsg::Compound {
+                pool,
+                rewards: rewards.into(),
+                min_out,
+                max_spread,
+                swap_routes,
             },
             funds,
         )?);
@@ -5,11 +5,17 @@
 //! in the correct ratio, the contract will swap some of the assets so that the
 //! ratio of assets are the same as the pools reserves after the swap.
 
+pub mod balancing;
 pub mod contract;
 mod error;
+pub mod generator;
 pub mod helpers;
 pub mod math;
+mod migrations;
 pub mod msg;
+pub mod pool;
+pub mod router;
 pub mod state;
+pub mod strategy;
 
 pub use crate::error::ContractError;
@@ -4,6 +4,8 @@ use cosmwasm_std::{Decimal, Decimal256, StdError, StdResult, Uint128, Uint256};
 use cw_asset::Asset;
 use cw_bigint::BigInt;
 
+pub mod pcl;
+
 /// Returns square root of a BigInt
 fn bigint_sqrt(input: BigInt) -> StdResult<BigInt> {
     if input < 0.into() {
@@ -47,6 +49,10 @@ fn bigint_to_uint128(input: BigInt) -> StdResult<Uint128> {
 /// swap to the other in order to have the same ratio of assets as the pool, so
 /// that we can then provide liquidity and get the most amount of LP tokens.
 ///
+/// `fee` is the total fee rate of the pool, and `maker_fee` the share of the
+/// commission that is sent out of the pool to the maker, so that only the rest
+/// of the commission stays in the reserves.
+///
 /// Returns `(offer_asset, return_asset): (Asset,Asset)` containing the amount
 /// and info of the asset we need to swap, and the asset that will be returned
 /// from the swap
@@ -55,6 +61,7 @@ pub fn calc_xyk_balancing_swap(
     reserve1: Uint128,
     reserve2: Uint128,
     fee: Decimal,
+    maker_fee: Decimal,
 ) -> StdResult<(Asset, Asset)> {
     // Instead of trying to implement our own big decimal, we just use BigInt
     // and multiply and divide with this number before and after doing
@@ -93,11 +100,19 @@ pub fn calc_xyk_balancing_swap(
         };
 
     let fee_int = (BigInt::from(fee.atomics().u128()) * &precision) / BigInt::from(10u128.pow(18));
+    let maker_fee_int =
+        (BigInt::from(maker_fee.atomics().u128()) * &precision) / BigInt::from(10u128.pow(18));
 
-    // Calculate amount to swap by solving quadratic equation
-    let a = &ask_reserve + &ask_balance;
+    // Calculate amount to swap by solving quadratic equation. The maker fee
+    // leaves the pool, which lowers the ask reserve after the swap, while the
+    // rest of the commission stays in it.
+    let a = &ask_reserve + &ask_balance
+        - (&ask_reserve * &fee_int * &maker_fee_int) / &precision / &precision;
     let b = 2u128 * &offer_reserve * (&ask_reserve + &ask_balance)
-        - ((&offer_reserve + &offer_balance) * &ask_reserve * &fee_int) / &precision;
+        - ((&offer_reserve + &offer_balance * (&precision - &maker_fee_int) / &precision)
+            * &ask_reserve
+            * &fee_int)
+            / &precision;
     let c = &offer_reserve * (&offer_reserve * &ask_balance - &offer_balance * &ask_reserve);
     let discriminant = &b * &b - (4u128 * &a * &c);
     //  We know that for this equation, there is only one positive real solution
@@ -125,13 +140,550 @@ pub fn calc_xyk_balancing_swap(
     Ok((offer_asset, return_asset))
 }
 
+/// For a constant product pool, calculates how much of one asset we need to
+/// swap to the other so that the value of our assets, at the price of the pool
+/// after the swap, is split according to `weights`. This is the counterpart of
+/// `calc_xyk_balancing_swap` used when withdrawing liquidity into a target
+/// ratio, and equal weights give the same swap.
+///
+/// Returns `(offer_asset, return_asset): (Asset,Asset)` containing the amount
+/// and info of the asset we need to swap, and the asset that will be returned
+/// from the swap
+pub fn calc_xyk_weighted_swap(
+    assets: [Asset; 2],
+    weights: [Decimal; 2],
+    reserve1: Uint128,
+    reserve2: Uint128,
+    fee: Decimal,
+) -> StdResult<(Asset, Asset)> {
+    // Make sure there is liquidity in the pool
+    if reserve1.is_zero() || reserve2.is_zero() {
+        return Err(StdError::generic_err("No liquidity in pool"));
+    }
+
+    // Returns whether we still hold at least the target value of the offer
+    // asset after swapping `offer_amount` of it. Comparing
+    //   (offer_balance - x) * price * ask_weight
+    // with
+    //   (ask_balance + y) * offer_weight
+    // where price is the price of the offer asset after the swap.
+    let has_excess = |offer: usize, offer_amount: Uint128| -> StdResult<bool> {
+        let ask = 1 - offer;
+        let reserves = [reserve1, reserve2];
+        let return_amount =
+            constant_product_formula(reserves[offer], reserves[ask], offer_amount, fee)?;
+
+        let offer_value = BigInt::from((assets[offer].amount - offer_amount).u128())
+            * BigInt::from((reserves[ask] - return_amount).u128())
+            * BigInt::from(weights[ask].atomics().u128());
+        let ask_value = BigInt::from((assets[ask].amount + return_amount).u128())
+            * BigInt::from((reserves[offer] + offer_amount).u128())
+            * BigInt::from(weights[offer].atomics().u128());
+        Ok(offer_value >= ask_value)
+    };
+
+    // Check which asset to swap
+    let offer = if has_excess(0, Uint128::zero())? {
+        0
+    } else {
+        1
+    };
+    let ask = 1 - offer;
+
+    // Find the largest amount that still leaves the offer asset in excess.
+    // The value of the offer asset only decreases with the amount swapped, so
+    // we can binary search for it.
+    let mut low = Uint128::zero();
+    let mut high = assets[offer].amount;
+    while low < high {
+        let mid = low + (high - low + Uint128::one()) / Uint128::from(2u8);
+        if has_excess(offer, mid)? {
+            low = mid;
+        } else {
+            high = mid - Uint128::one();
+        }
+    }
+
+    let offer_asset = Asset {
+        amount: low,
+        info: assets[offer].info.clone(),
+    };
+    let return_amount = constant_product_formula(
+        [reserve1, reserve2][offer],
+        [reserve1, reserve2][ask],
+        low,
+        fee,
+    )?;
+    let return_asset = Asset {
+        amount: return_amount,
+        info: assets[ask].info.clone(),
+    };
+
+    Ok((offer_asset, return_asset))
+}
+
+/// Number of assets in the StableSwap pools handled here
+const N_COINS: u128 = 2;
+
+/// Maximum number of Newton iterations when solving the StableSwap invariant
+const MAX_ITERATIONS: usize = 64;
+
+/// Scale of the atomics of a Decimal
+const DECIMAL_FRACTIONAL: u128 = 1_000_000_000_000_000_000;
+
+/// Returns whether two BigInts differ by at most one
+fn bigint_converged(a: &BigInt, b: &BigInt) -> bool {
+    let diff = if a > b { a - b } else { b - a };
+    diff <= BigInt::from(1u128)
+}
+
+/// Calculates the StableSwap invariant D for the given normalized reserves.
+/// The amplification is passed as `amp` times `DECIMAL_FRACTIONAL`.
+fn stable_invariant(reserves: &[BigInt; 2], amp: &BigInt) -> StdResult<BigInt> {
+    let sum = &reserves[0] + &reserves[1];
+    if sum == BigInt::from(0u128) {
+        return Ok(sum);
+    }
+
+    // Ann = amp * N, kept as a fraction of DECIMAL_FRACTIONAL
+    let leverage = N_COINS * amp;
+    let fractional = BigInt::from(DECIMAL_FRACTIONAL);
+
+    let mut d = sum.clone();
+    for _ in 0..MAX_ITERATIONS {
+        let mut d_prod = d.clone();
+        for reserve in reserves.iter() {
+            d_prod = &d_prod * &d / (N_COINS * reserve);
+        }
+        let d_prev = d.clone();
+        d = (&leverage * &sum + N_COINS * &fractional * &d_prod) * &d
+            / ((&leverage - &fractional) * &d + (N_COINS + 1u128) * &fractional * &d_prod);
+        if bigint_converged(&d, &d_prev) {
+            return Ok(d);
+        }
+    }
+
+    Err(StdError::generic_err(
+        "StableSwap invariant did not converge",
+    ))
+}
+
+/// Calculates the normalized reserve of one asset that keeps the invariant at
+/// `d` when the reserve of the other asset is `x`.
+fn stable_other_reserve(x: &BigInt, d: &BigInt, amp: &BigInt) -> StdResult<BigInt> {
+    let leverage = N_COINS * amp;
+    let fractional = BigInt::from(DECIMAL_FRACTIONAL);
+
+    let c = d * d / (N_COINS * x) * d * &fractional / (N_COINS * &leverage);
+    let b = x + d * &fractional / &leverage;
+
+    let mut y = d.clone();
+    for _ in 0..MAX_ITERATIONS {
+        let y_prev = y.clone();
+        y = (&y * &y + &c) / (2u128 * &y + &b - d);
+        if bigint_converged(&y, &y_prev) {
+            return Ok(y);
+        }
+    }
+
+    Err(StdError::generic_err(
+        "StableSwap invariant did not converge",
+    ))
+}
+
+/// Calculates how much will be returned from a swap in a StableSwap pool, in
+/// normalized amounts. `d` is the invariant of the reserves, which is passed
+/// in so that it is only calculated once when simulating many swaps.
+fn stable_swap_formula(
+    offer_reserve: &BigInt,
+    ask_reserve: &BigInt,
+    offer_amount: &BigInt,
+    d: &BigInt,
+    amp: &BigInt,
+    fee: &BigInt,
+) -> StdResult<BigInt> {
+    let new_ask_reserve = stable_other_reserve(&(offer_reserve + offer_amount), d, amp)?;
+
+    // Round down by one, in the favor of the pool
+    let zero = BigInt::from(0u128);
+    let return_amount = ask_reserve - &new_ask_reserve - 1u128;
+    if return_amount <= zero {
+        return Ok(zero);
+    }
+    let commission_amount = &return_amount * fee / DECIMAL_FRACTIONAL;
+    Ok(return_amount - commission_amount)
+}
+
+/// Calculates the share of the pool minted as LP tokens when providing
+/// `amounts` to a StableSwap pool with the given `reserves`, all normalized.
+/// Each asset is charged an imbalance fee on the amount it differs from a
+/// deposit in the pool ratio. Returns the share as a `(numerator,
+/// denominator)` fraction.
+fn stable_provide_share(
+    amounts: &[BigInt; 2],
+    reserves: &[BigInt; 2],
+    amp: &BigInt,
+    fee: &BigInt,
+) -> StdResult<(BigInt, BigInt)> {
+    let d_before = stable_invariant(reserves, amp)?;
+    let new_reserves = [&reserves[0] + &amounts[0], &reserves[1] + &amounts[1]];
+    let d_after = stable_invariant(&new_reserves, amp)?;
+
+    // The imbalance fee is fee * N / (4 * (N - 1)), which is half the swap fee
+    // for two assets
+    let mut charged_reserves = new_reserves.clone();
+    for (i, charged_reserve) in charged_reserves.iter_mut().enumerate() {
+        let ideal_reserve = &d_after * &reserves[i] / &d_before;
+        let difference = if ideal_reserve > new_reserves[i] {
+            &ideal_reserve - &new_reserves[i]
+        } else {
+            &new_reserves[i] - &ideal_reserve
+        };
+        *charged_reserve = &*charged_reserve - fee * &difference / (2u128 * DECIMAL_FRACTIONAL);
+    }
+    let d_charged = stable_invariant(&charged_reserves, amp)?;
+
+    Ok((d_charged - &d_before, d_before))
+}
+
+/// Maximum number of iterations of the search in `find_best_swap_amount`,
+/// to keep its gas usage predictable
+const MAX_SEARCH_ITERATIONS: usize = 48;
+
+/// The search in `find_best_swap_amount` stops once the range of swap amounts
+/// left is at most one part in this many of the largest swap amount
+const SEARCH_TOLERANCE: u128 = 1_000_000;
+
+/// Searches for the amount between zero and `max_amount` to swap before
+/// providing liquidity that gives the largest share of the pool.
+/// `provide_share` returns the share as a `(numerator, denominator)` fraction
+/// for a given swap amount, together with the amount returned from the swap.
+///
+/// The share first increases and then decreases with the amount swapped, so
+/// we can ternary search for the largest one. The search stops when the range
+/// left is within `SEARCH_TOLERANCE` of `max_amount`, or after
+/// `MAX_SEARCH_ITERATIONS` iterations. Not swapping at all is picked unless
+/// the swap found is strictly better, since the share is nearly flat around
+/// its maximum when swapping costs about as much as the imbalance.
+///
+/// Returns `(offer_amount, return_amount)`.
+fn find_best_swap_amount<F>(max_amount: Uint128, provide_share: F) -> StdResult<(Uint128, Uint128)>
+where
+    F: Fn(Uint128) -> StdResult<((BigInt, BigInt), Uint128)>,
+{
+    let is_better = |a: &(BigInt, BigInt), b: &(BigInt, BigInt)| &a.0 * &b.1 > &b.0 * &a.1;
+
+    let mut low = Uint128::zero();
+    let mut high = max_amount;
+    let three = Uint128::from(3u8);
+    let tolerance = std::cmp::max(
+        max_amount / Uint128::from(SEARCH_TOLERANCE),
+        Uint128::from(2u8),
+    );
+    for _ in 0..MAX_SEARCH_ITERATIONS {
+        if high - low <= tolerance {
+            break;
+        }
+        let mid1 = low + (high - low) / three;
+        let mid2 = high - (high - low) / three;
+        if is_better(&provide_share(mid2)?.0, &provide_share(mid1)?.0) {
+            low = mid1 + Uint128::one();
+        } else {
+            high = mid2 - Uint128::one();
+        }
+    }
+
+    // Pick the best of not swapping and the ends and middle of the range
+    // left, preferring smaller swaps
+    let (mut best_share, mut return_amount) = provide_share(Uint128::zero())?;
+    let mut offer_amount = Uint128::zero();
+    for amount in [low, low + (high - low) / Uint128::from(2u8), high] {
+        let (share, amount_returned) = provide_share(amount)?;
+        if is_better(&share, &best_share) {
+            best_share = share;
+            return_amount = amount_returned;
+            offer_amount = amount;
+        }
+    }
+
+    Ok((offer_amount, return_amount))
+}
+
+/// Returns the factors that amounts of assets with the given `precisions` are
+/// multiplied with to normalize them to the largest of the precisions
+fn precision_scales(precisions: [u8; 2]) -> [BigInt; 2] {
+    let max_precision = std::cmp::max(precisions[0], precisions[1]);
+    [
+        BigInt::from(10u128.pow((max_precision - precisions[0]).into())),
+        BigInt::from(10u128.pow((max_precision - precisions[1]).into())),
+    ]
+}
+
+/// For a StableSwap pool, calculates how much of one asset we need to swap to
+/// the other before providing liquidity so that we get the most amount of LP
+/// tokens. Unlike constant product pools, StableSwap pools accept liquidity in
+/// any ratio, but charge an imbalance fee on deposits that are not in the pool
+/// ratio. The swap amount is found by searching for the largest LP output, and
+/// may be zero when swapping costs more than the imbalance fee.
+///
+/// `precisions` are the decimals of the two assets, which the pool normalizes
+/// amounts to before using them in the invariant.
+///
+/// Returns `(offer_asset, return_asset): (Asset,Asset)` containing the amount
+/// and info of the asset we need to swap, and the asset that will be returned
+/// from the swap
+pub fn calc_stable_balancing_swap(
+    assets: [Asset; 2],
+    reserves: [Uint128; 2],
+    precisions: [u8; 2],
+    amp: Decimal,
+    fee: Decimal,
+) -> StdResult<(Asset, Asset)> {
+    // Make sure there is liquidity in the pool
+    if reserves[0].is_zero() || reserves[1].is_zero() {
+        return Err(StdError::generic_err("No liquidity in pool"));
+    }
+
+    let scales = precision_scales(precisions);
+    let amp = BigInt::from(amp.atomics().u128());
+    let fee_int = BigInt::from(fee.atomics().u128());
+    let balances = [
+        BigInt::from(assets[0].amount.u128()) * &scales[0],
+        BigInt::from(assets[1].amount.u128()) * &scales[1],
+    ];
+    let reserves = [
+        BigInt::from(reserves[0].u128()) * &scales[0],
+        BigInt::from(reserves[1].u128()) * &scales[1],
+    ];
+
+    // Offer the asset that we have more of relative to the pool reserves
+    let offer = if &balances[0] * &reserves[1] >= &balances[1] * &reserves[0] {
+        0
+    } else {
+        1
+    };
+    let ask = 1 - offer;
+    let d = stable_invariant(&reserves, &amp)?;
+
+    // Returns the share of the pool minted when swapping `offer_amount` and
+    // then providing, together with the amount returned from the swap
+    let provide_share = |offer_amount: Uint128| -> StdResult<((BigInt, BigInt), Uint128)> {
+        let offer_int = BigInt::from(offer_amount.u128()) * &scales[offer];
+        let return_amount = bigint_to_uint128(
+            stable_swap_formula(
+                &reserves[offer],
+                &reserves[ask],
+                &offer_int,
+                &d,
+                &amp,
+                &fee_int,
+            )? / &scales[ask],
+        )?;
+        let return_int = BigInt::from(return_amount.u128()) * &scales[ask];
+
+        let mut amounts = balances.clone();
+        let mut new_reserves = reserves.clone();
+        amounts[offer] = &amounts[offer] - &offer_int;
+        amounts[ask] = &amounts[ask] + &return_int;
+        new_reserves[offer] = &new_reserves[offer] + &offer_int;
+        new_reserves[ask] = &new_reserves[ask] - &return_int;
+
+        let share = stable_provide_share(&amounts, &new_reserves, &amp, &fee_int)?;
+        Ok((share, return_amount))
+    };
+    let (offer_amount, return_amount) = find_best_swap_amount(assets[offer].amount, provide_share)?;
+
+    Ok((
+        Asset {
+            amount: offer_amount,
+            info: assets[offer].info.clone(),
+        },
+        Asset {
+            amount: return_amount,
+            info: assets[ask].info.clone(),
+        },
+    ))
+}
+
+/// Calculates the amount of LP tokens minted when providing `amounts` to a
+/// StableSwap pool with the given `reserves` and `total_share` of LP tokens,
+/// including the imbalance fee. The amounts, reserves and precisions must be
+/// in the same order.
+pub fn calc_stable_lp_out(
+    amounts: [Uint128; 2],
+    reserves: [Uint128; 2],
+    precisions: [u8; 2],
+    amp: Decimal,
+    fee: Decimal,
+    total_share: Uint128,
+) -> StdResult<Uint128> {
+    if reserves[0].is_zero() || reserves[1].is_zero() || total_share.is_zero() {
+        return Err(StdError::generic_err("No liquidity in pool"));
+    }
+
+    let scales = precision_scales(precisions);
+    let (numerator, denominator) = stable_provide_share(
+        &[
+            BigInt::from(amounts[0].u128()) * &scales[0],
+            BigInt::from(amounts[1].u128()) * &scales[1],
+        ],
+        &[
+            BigInt::from(reserves[0].u128()) * &scales[0],
+            BigInt::from(reserves[1].u128()) * &scales[1],
+        ],
+        &BigInt::from(amp.atomics().u128()),
+        &BigInt::from(fee.atomics().u128()),
+    )?;
+
+    bigint_to_uint128(BigInt::from(total_share.u128()) * numerator / denominator)
+}
+
+/// Calculates how much `offer_amount` is worth in the ask asset at the spot
+/// price of a StableSwap pool, which Astroport takes as one to one once the
+/// amounts are normalized. `precisions` are the decimals of the offer and ask
+/// assets, in that order.
+pub fn calc_stable_spot_return(offer_amount: Uint128, precisions: [u8; 2]) -> StdResult<Uint128> {
+    let scales = precision_scales(precisions);
+    bigint_to_uint128(BigInt::from(offer_amount.u128()) * &scales[0] / &scales[1])
+}
+
+/// Calculates the amount of LP tokens minted when providing `amounts` to a
+/// constant product pool with the given `reserves` and `total_share` of LP
+/// tokens. The amounts and reserves must be in the same order.
+pub fn calc_xyk_lp_out(
+    amounts: [Uint128; 2],
+    reserves: [Uint128; 2],
+    total_share: Uint128,
+) -> StdResult<Uint128> {
+    if reserves[0].is_zero() || reserves[1].is_zero() || total_share.is_zero() {
+        return Err(StdError::generic_err("No liquidity in pool"));
+    }
+
+    // Astroport mints LP tokens according to the asset with the lowest share
+    // of the reserves
+    Ok(std::cmp::min(
+        amounts[0].multiply_ratio(total_share, reserves[0]),
+        amounts[1].multiply_ratio(total_share, reserves[1]),
+    ))
+}
+
+/// Calculates how much `offer_amount` is worth in the ask asset at the spot
+/// price of a constant product pool with the given reserves, before fees and
+/// price impact
+pub fn calc_xyk_spot_return(
+    offer_amount: Uint128,
+    offer_reserve: Uint128,
+    ask_reserve: Uint128,
+) -> StdResult<Uint128> {
+    if offer_reserve.is_zero() {
+        return Err(StdError::generic_err("No liquidity in pool"));
+    }
+    Ok((offer_amount.full_mul(ask_reserve) / Uint256::from(offer_reserve)).try_into()?)
+}
+
+/// Maximum number of swap simulations done by
+/// `calc_simulated_balancing_swap`, to keep its gas usage predictable
+const MAX_SIMULATION_ITERATIONS: usize = 32;
+
+/// The ratios of the assets and the reserves after a simulated swap are
+/// considered equal when they differ by at most one part in this many
+const SIMULATION_TOLERANCE: u128 = 10_000;
+
+/// Calculates how much of one asset we need to swap to the other so that the
+/// ratio of our assets is the same as the ratio of the pool reserves after the
+/// swap, for pools where we have no closed-form solution. Instead the offer
+/// amount is found with a binary search, using `simulate` to get the amount
+/// returned from the pool for a given offer amount.
+///
+/// The search stops when the ratios are within `SIMULATION_TOLERANCE` of each
+/// other, or after `MAX_SIMULATION_ITERATIONS` simulations, in which case the
+/// largest offer amount found that does not overshoot is used.
+///
+/// Returns `(offer_asset, return_asset): (Asset,Asset)` containing the amount
+/// and info of the asset we need to swap, and the asset that will be returned
+/// from the swap
+pub fn calc_simulated_balancing_swap<F>(
+    assets: [Asset; 2],
+    reserves: [Uint128; 2],
+    simulate: F,
+) -> StdResult<(Asset, Asset)>
+where
+    F: Fn(&Asset) -> StdResult<Uint128>,
+{
+    // Make sure there is liquidity in the pool
+    if reserves[0].is_zero() || reserves[1].is_zero() {
+        return Err(StdError::generic_err("No liquidity in pool"));
+    }
+
+    // Offer the asset that we have more of relative to the pool reserves
+    let offer = if assets[0].amount.full_mul(reserves[1]) >= assets[1].amount.full_mul(reserves[0])
+    {
+        0
+    } else {
+        1
+    };
+    let ask = 1 - offer;
+
+    // Returns the cross products of our assets and the reserves after swapping
+    // `offer_amount` for `return_amount`. The first one is larger as long as
+    // we still have too much of the offer asset.
+    let cross_products =
+        |offer_amount: Uint128, return_amount: Uint128| -> StdResult<(Uint256, Uint256)> {
+            let offer_balance = assets[offer].amount.checked_sub(offer_amount)?;
+            let ask_balance = assets[ask].amount.checked_add(return_amount)?;
+            let offer_reserve = reserves[offer].checked_add(offer_amount)?;
+            let ask_reserve = reserves[ask].checked_sub(return_amount)?;
+            Ok((
+                offer_balance.full_mul(ask_reserve),
+                ask_balance.full_mul(offer_reserve),
+            ))
+        };
+    let converged = |(a, b): (Uint256, Uint256)| {
+        let diff = if a > b { a - b } else { b - a };
+        diff * Uint256::from(SIMULATION_TOLERANCE) <= std::cmp::max(a, b)
+    };
+
+    let mut low = (Uint128::zero(), Uint128::zero());
+    let mut high = assets[offer].amount;
+    if !converged(cross_products(low.0, low.1)?) {
+        for _ in 0..MAX_SIMULATION_ITERATIONS {
+            if high - low.0 <= Uint128::one() {
+                break;
+            }
+            let mid = low.0 + (high - low.0) / Uint128::from(2u8);
+            let return_amount = simulate(&Asset::new(assets[offer].info.clone(), mid))?;
+            let products = cross_products(mid, return_amount)?;
+            if converged(products) {
+                low = (mid, return_amount);
+                break;
+            }
+            if products.0 > products.1 {
+                low = (mid, return_amount);
+            } else {
+                high = mid;
+            }
+        }
+    }
+
+    Ok((
+        Asset::new(assets[offer].info.clone(), low.0),
+        Asset::new(assets[ask].info.clone(), low.1),
+    ))
+}
+
 #[cfg(test)]
 mod test {
-    use cosmwasm_std::{Decimal, Uint128};
+    use cosmwasm_std::{Decimal, StdResult, Uint128};
     use cw_asset::{Asset, AssetInfo};
     use cw_bigint::BigInt;
+    use std::cell::Cell;
 
-    use crate::math::{bigint_sqrt, calc_xyk_balancing_swap};
+    use crate::math::{
+        bigint_sqrt, calc_simulated_balancing_swap, calc_stable_balancing_swap, calc_stable_lp_out,
+        calc_stable_spot_return, calc_xyk_balancing_swap, calc_xyk_lp_out, calc_xyk_spot_return,
+        calc_xyk_weighted_swap, constant_product_formula, find_best_swap_amount,
+        MAX_SEARCH_ITERATIONS,
+    };
 
     /// Assert that two Decimals are almost the same (diff smaller than one permille)
     fn assert_decimal_almost_eq(a: Decimal, b: Decimal) {
@@ -145,7 +697,10 @@ mod test {
         }
     }
 
-    // Assert that the ratio of the users assets is the same as the pool after the swap
+    // Assert that the ratio of the users assets is the same as the pool after
+    // the swap, with a relative difference of at most `tolerance`. The
+    // `maker_fee_amount` is sent out of the pool by the swap.
+    #[allow(clippy::too_many_arguments)]
     fn assert_asset_ratios_same_after_swap(
         offer_reserve: Uint128,
         ask_reserve: Uint128,
@@ -153,16 +708,22 @@ mod test {
         ask_balance: Uint128,
         offer_amount: Uint128,
         return_amount: Uint128,
+        maker_fee_amount: Uint128,
+        tolerance: Decimal,
     ) {
         let asset_ratio_after_swap =
             Decimal::from_ratio(ask_balance + return_amount, offer_balance - offer_amount);
-        let reserve_ratio_after_swap =
-            Decimal::from_ratio(ask_reserve - return_amount, offer_reserve + offer_amount);
-        println!(
-            "asset_ratio_after_swap: {}, reserve_ratio_after_swap: {}",
-            asset_ratio_after_swap, reserve_ratio_after_swap
+        let reserve_ratio_after_swap = Decimal::from_ratio(
+            ask_reserve - return_amount - maker_fee_amount,
+            offer_reserve + offer_amount,
         );
-        assert_decimal_almost_eq(asset_ratio_after_swap, reserve_ratio_after_swap);
+        let ratio = asset_ratio_after_swap / reserve_ratio_after_swap;
+        let diff = if ratio > Decimal::one() {
+            ratio - Decimal::one()
+        } else {
+            Decimal::one() - ratio
+        };
+        assert!(diff <= tolerance, "relative diff {} too large", diff);
     }
 
     #[test]
@@ -250,50 +811,225 @@ mod test {
             ),
         ];
 
-        // Same fee for all test cases
+        // Same total fee for all test cases, with either a third of the
+        // commission sent to the maker or all of it kept in the pool
         let fee = Decimal::permille(3);
+        let maker_fees = [Decimal::from_ratio(1u128, 3u128), Decimal::zero()];
 
         // Run test cases
-        for (i, (assets, reserve1, reserve2)) in test_cases.into_iter().enumerate() {
-            println!("Test case {}", i + 1);
-            println!("Assets: {:?}", assets);
-            println!("Reserves: {}, {}", reserve1, reserve2);
-
-            // Compare ratios to define offer asset
-            let asset_ratio = Decimal::from_ratio(assets[0].amount, assets[1].amount);
-            let reserve_ratio = Decimal::from_ratio(reserve1, reserve2);
-            let (offer_asset, ask_asset, offer_reserve, ask_reserve) =
-                if asset_ratio > reserve_ratio {
-                    (assets[0].clone(), assets[1].clone(), reserve1, reserve2)
-                } else {
-                    (assets[1].clone(), assets[0].clone(), reserve2, reserve1)
-                };
-
-            // Calculate swap
-            let (swap_asset, return_asset) =
-                calc_xyk_balancing_swap(assets.clone(), reserve1, reserve2, fee).unwrap();
-
-            // If ratios are already almost the same, no swap should happen
-            if asset_ratio == reserve_ratio {
-                assert_eq!(swap_asset.amount, Uint128::zero());
-            }
+        for maker_fee in maker_fees {
+            for (assets, reserve1, reserve2) in test_cases.iter().cloned() {
+                // Compare ratios to define offer asset
+                let asset_ratio = Decimal::from_ratio(assets[0].amount, assets[1].amount);
+                let reserve_ratio = Decimal::from_ratio(reserve1, reserve2);
+                let (offer_asset, ask_asset, offer_reserve, ask_reserve) =
+                    if asset_ratio > reserve_ratio {
+                        (assets[0].clone(), assets[1].clone(), reserve1, reserve2)
+                    } else {
+                        (assets[1].clone(), assets[0].clone(), reserve2, reserve1)
+                    };
 
-            // Assert that the correct asset is being offered
-            assert_eq!(swap_asset.info, offer_asset.info);
-
-            // Assert that the asset ratio and the pool ratio are the same after the swap
-            assert_asset_ratios_same_after_swap(
-                offer_reserve,
-                ask_reserve,
-                offer_asset.amount,
-                ask_asset.amount,
-                swap_asset.amount,
-                return_asset.amount,
-            );
-            println!("------------------------------------");
+                // Calculate swap
+                let (swap_asset, return_asset) =
+                    calc_xyk_balancing_swap(assets.clone(), reserve1, reserve2, fee, maker_fee)
+                        .unwrap();
+                let commission_amount = constant_product_formula(
+                    offer_reserve,
+                    ask_reserve,
+                    swap_asset.amount,
+                    Decimal::zero(),
+                )
+                .unwrap()
+                    - return_asset.amount;
+
+                // If ratios are already almost the same, no swap should happen
+                if asset_ratio == reserve_ratio {
+                    assert_eq!(swap_asset.amount, Uint128::zero());
+                }
+
+                // Assert that the correct asset is being offered
+                assert_eq!(swap_asset.info, offer_asset.info);
+
+                // Assert that the asset ratio and the pool ratio are the same
+                // after the swap
+                assert_asset_ratios_same_after_swap(
+                    offer_reserve,
+                    ask_reserve,
+                    offer_asset.amount,
+                    ask_asset.amount,
+                    swap_asset.amount,
+                    return_asset.amount,
+                    commission_amount * maker_fee,
+                    Decimal::from_ratio(1u128, 100_000u128),
+                );
+            }
         }
     }
 
+    #[test]
+    fn test_calc_xyk_weighted_swap() {
+        let fee = Decimal::permille(3);
+        let assets = [
+            Asset::native("uatom", 1_000_000u128),
+            Asset::native("uosmo", 2_000_000u128),
+        ];
+        let reserve1 = Uint128::from(1_000_000_000_000u128);
+        let reserve2 = Uint128::from(2_000_000_000_000u128);
+
+        // Equal weights give the same swap as balancing
+        let imbalanced_assets = [
+            Asset::native("uatom", 1_000_000u128),
+            Asset::native("uosmo", 1_000_000u128),
+        ];
+        let swap = calc_xyk_weighted_swap(
+            imbalanced_assets.clone(),
+            [Decimal::percent(50), Decimal::percent(50)],
+            reserve1,
+            reserve2,
+            fee,
+        )
+        .unwrap();
+        let balancing_swap =
+            calc_xyk_balancing_swap(imbalanced_assets, reserve1, reserve2, fee, Decimal::zero())
+                .unwrap();
+        assert_eq!(swap, balancing_swap);
+
+        // 80% uatom, 20% uosmo should swap uosmo for uatom
+        let (offer_asset, return_asset) = calc_xyk_weighted_swap(
+            assets.clone(),
+            [Decimal::percent(80), Decimal::percent(20)],
+            reserve1,
+            reserve2,
+            fee,
+        )
+        .unwrap();
+        assert_eq!(offer_asset.info, assets[1].info);
+        let uatom_value = Decimal::from_ratio(
+            (assets[0].amount + return_asset.amount) * Uint128::from(2u8),
+            1u8,
+        );
+        let uosmo_value = Decimal::from_ratio(assets[1].amount - offer_asset.amount, 1u8);
+        assert_decimal_almost_eq(
+            uatom_value / (uatom_value + uosmo_value),
+            Decimal::percent(80),
+        );
+
+        // A weight of zero swaps everything
+        let (offer_asset, _) = calc_xyk_weighted_swap(
+            assets.clone(),
+            [Decimal::one(), Decimal::zero()],
+            reserve1,
+            reserve2,
+            fee,
+        )
+        .unwrap();
+        assert_eq!(offer_asset, assets[1]);
+    }
+
+    #[test]
+    fn test_calc_xyk_lp_out() {
+        let reserves = [
+            Uint128::from(1_000_000_000u128),
+            Uint128::from(2_000_000_000u128),
+        ];
+        let total_share = Uint128::from(1_414_213_562u128);
+
+        // Balanced amounts
+        let lp_out = calc_xyk_lp_out(
+            [Uint128::from(1_000_000u128), Uint128::from(2_000_000u128)],
+            reserves,
+            total_share,
+        )
+        .unwrap();
+        assert_eq!(lp_out, Uint128::from(1_414_213u128));
+
+        // Imbalanced amounts are limited by the smallest share
+        let lp_out = calc_xyk_lp_out(
+            [Uint128::from(1_000_000u128), Uint128::from(1_000_000u128)],
+            reserves,
+            total_share,
+        )
+        .unwrap();
+        assert_eq!(lp_out, Uint128::from(707_106u128));
+
+        // Empty pool
+        calc_xyk_lp_out(
+            [Uint128::from(1_000_000u128), Uint128::from(1_000_000u128)],
+            [Uint128::zero(), Uint128::zero()],
+            Uint128::zero(),
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn test_calc_simulated_balancing_swap() {
+        let fee = Decimal::permille(3);
+        let assets = [
+            Asset::native("uatom", 1_000_000_000_000u128),
+            Asset::native("uosmo", 1_000_000_000_000u128),
+        ];
+        let reserves = [
+            Uint128::from(1_000_000_000_000u128),
+            Uint128::from(3_000_000_000_000u128),
+        ];
+
+        // Simulate swaps in a constant product pool, counting the simulations
+        let simulations = Cell::new(0);
+        let simulate = |offer_asset: &Asset| {
+            simulations.set(simulations.get() + 1);
+            let (offer_reserve, ask_reserve) = if offer_asset.info == assets[0].info {
+                (reserves[0], reserves[1])
+            } else {
+                (reserves[1], reserves[0])
+            };
+            constant_product_formula(offer_reserve, ask_reserve, offer_asset.amount, fee)
+        };
+
+        let (offer_asset, return_asset) =
+            calc_simulated_balancing_swap(assets.clone(), reserves, simulate).unwrap();
+        assert_eq!(offer_asset.info, assets[0].info);
+        assert_eq!(return_asset.info, assets[1].info);
+        assert!(simulations.get() <= super::MAX_SIMULATION_ITERATIONS);
+        assert_asset_ratios_same_after_swap(
+            reserves[0],
+            reserves[1],
+            assets[0].amount,
+            assets[1].amount,
+            offer_asset.amount,
+            return_asset.amount,
+            Uint128::zero(),
+            Decimal::permille(1),
+        );
+
+        // The search should end up close to the closed-form solution
+        let (expected_offer, _) = calc_xyk_balancing_swap(
+            assets.clone(),
+            reserves[0],
+            reserves[1],
+            fee,
+            Decimal::zero(),
+        )
+        .unwrap();
+        assert_decimal_almost_eq(
+            Decimal::from_ratio(offer_asset.amount, expected_offer.amount),
+            Decimal::one(),
+        );
+
+        // Assets already in the pool ratio are not swapped, without simulating
+        simulations.set(0);
+        let (offer_asset, _) = calc_simulated_balancing_swap(
+            [
+                Asset::native("uatom", 1_000_000u128),
+                Asset::native("uosmo", 3_000_000u128),
+            ],
+            reserves,
+            simulate,
+        )
+        .unwrap();
+        assert_eq!(offer_asset.amount, Uint128::zero());
+        assert_eq!(simulations.get(), 0);
+    }
+
     #[test]
     fn test_bigint_sqrt() {
         // Test the sqrt algorithm
@@ -347,4 +1083,150 @@ mod test {
             assert_eq!(result, expected);
         }
     }
+
+    #[test]
+    fn test_calc_stable_balancing_swap() {
+        let fee = Decimal::permille(3);
+        let total_share = Uint128::from(1_000_000_000_000u128);
+        let reserves = [
+            Uint128::from(1_000_000_000_000u128),
+            Uint128::from(1_000_000_000_000u128),
+        ];
+
+        // Assets already in the pool ratio should not be swapped
+        let (offer_asset, _) = calc_stable_balancing_swap(
+            [
+                Asset::native("uusdc", 1_000_000u128),
+                Asset::native("uusdt", 1_000_000u128),
+            ],
+            reserves,
+            [6, 6],
+            Decimal::from_ratio(100u128, 1u128),
+            fee,
+        )
+        .unwrap();
+        assert_eq!(offer_asset.amount, Uint128::zero());
+
+        // A large single sided deposit with low amplification is worth
+        // balancing, but swapping half of it would cost more than the
+        // imbalance fee
+        let assets = [
+            Asset::native("uusdc", 1_000_000_000_000u128),
+            Asset::native("uusdt", 0u128),
+        ];
+        let amp = Decimal::one();
+        let (offer_asset, return_asset) =
+            calc_stable_balancing_swap(assets.clone(), reserves, [6, 6], amp, fee).unwrap();
+        assert_eq!(offer_asset.info, assets[0].info);
+        assert!(offer_asset.amount > Uint128::zero());
+        assert!(offer_asset.amount < assets[0].amount / Uint128::from(2u8));
+
+        let lp_out_without_swap = calc_stable_lp_out(
+            [assets[0].amount, assets[1].amount],
+            reserves,
+            [6, 6],
+            amp,
+            fee,
+            total_share,
+        )
+        .unwrap();
+        let lp_out_with_swap = calc_stable_lp_out(
+            [
+                assets[0].amount - offer_asset.amount,
+                assets[1].amount + return_asset.amount,
+            ],
+            [
+                reserves[0] + offer_asset.amount,
+                reserves[1] - return_asset.amount,
+            ],
+            [6, 6],
+            amp,
+            fee,
+            total_share,
+        )
+        .unwrap();
+        assert!(lp_out_with_swap > lp_out_without_swap);
+
+        // The same pool with an 18 decimal asset should give the same swap
+        let (offer_asset_18, return_asset_18) = calc_stable_balancing_swap(
+            assets,
+            [reserves[0], reserves[1] * Uint128::from(10u128.pow(12))],
+            [6, 18],
+            amp,
+            fee,
+        )
+        .unwrap();
+        assert_decimal_almost_eq(
+            Decimal::from_ratio(offer_asset_18.amount, offer_asset.amount),
+            Decimal::one(),
+        );
+        assert_decimal_almost_eq(
+            Decimal::from_ratio(
+                return_asset_18.amount,
+                return_asset.amount * Uint128::from(10u128.pow(12)),
+            ),
+            Decimal::one(),
+        );
+    }
+
+    #[test]
+    fn test_find_best_swap_amount() {
+        // The share x * (max - x) / max^2 is largest at half of the max. The
+        // search stops within the tolerance after a bounded number of steps.
+        let max_amount = Uint128::from(u128::MAX / 4);
+        let evaluations = Cell::new(0);
+        let provide_share = |amount: Uint128| -> StdResult<((BigInt, BigInt), Uint128)> {
+            evaluations.set(evaluations.get() + 1);
+            let x = BigInt::from(amount.u128());
+            let max = BigInt::from(max_amount.u128());
+            Ok(((&x * (&max - &x), &max * &max), amount))
+        };
+
+        let (offer_amount, return_amount) =
+            find_best_swap_amount(max_amount, provide_share).unwrap();
+        assert_eq!(offer_amount, return_amount);
+        let half = max_amount / Uint128::from(2u8);
+        let diff = if offer_amount > half {
+            offer_amount - half
+        } else {
+            half - offer_amount
+        };
+        assert!(diff <= max_amount / Uint128::from(super::SEARCH_TOLERANCE));
+        assert!(evaluations.get() <= 2 * MAX_SEARCH_ITERATIONS + 4);
+
+        // Not swapping is preferred when swapping gives no larger share
+        let (offer_amount, _) = find_best_swap_amount(max_amount, |amount| {
+            Ok(((BigInt::from(1u128), BigInt::from(2u128)), amount))
+        })
+        .unwrap();
+        assert_eq!(offer_amount, Uint128::zero());
+    }
+
+    #[test]
+    fn test_calc_spot_return() {
+        // Constant product pools are priced at the ratio of the reserves
+        let spot_return = calc_xyk_spot_return(
+            Uint128::from(1_000u128),
+            Uint128::from(1_000_000u128),
+            Uint128::from(2_500_000u128),
+        )
+        .unwrap();
+        assert_eq!(spot_return, Uint128::from(2_500u128));
+
+        // A spot return that does not fit in 128 bits is an error
+        calc_xyk_spot_return(
+            Uint128::MAX,
+            Uint128::from(1_000_000u128),
+            Uint128::from(2_000_000u128),
+        )
+        .unwrap_err();
+        calc_xyk_spot_return(Uint128::one(), Uint128::zero(), Uint128::one()).unwrap_err();
+
+        // StableSwap pools are priced one to one after normalizing the amounts
+        let spot_return = calc_stable_spot_return(Uint128::from(1_000_000u128), [6, 18]).unwrap();
+        assert_eq!(spot_return, Uint128::from(1_000_000_000_000_000_000u128));
+        let spot_return =
+            calc_stable_spot_return(Uint128::from(1_000_000_000_000_000_000u128), [18, 6]).unwrap();
+        assert_eq!(spot_return, Uint128::from(1_000_000u128));
+    }
 }
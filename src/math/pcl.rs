@@ -0,0 +1,510 @@
+//! Module containing the calculations needed for balancing liquidity
+//! provisions to Astroport concentrated liquidity (PCL) pairs.
+//!
+//! PCL pairs use the two asset CryptoSwap invariant on reserves scaled by the
+//! pair's price scale, where `x0` is the first asset and `x1` the second asset
+//! multiplied by the price scale:
+//!
+//! ```text
+//! K0 = 4 * x0 * x1 / D^2
+//! K = A * K0 * gamma^2 / (gamma + 1 - K0)^2
+//! K * D * (x0 + x1) + x0 * x1 = K * D^2 + D^2 / 4
+//! ```
+//!
+//! The invariant is solved by bisection, and amounts are kept as BigInts with
+//! the parameters scaled by `DECIMAL_FRACTIONAL`. Repegging of the price scale
+//! after a swap is not modeled, since it only happens once the pair has made
+//! enough profit and does not change the amount returned from the swap.
+
+use cosmwasm_std::{Decimal, StdError, StdResult, Uint128};
+use cw_asset::Asset;
+use cw_bigint::BigInt;
+use serde::Deserialize;
+
+use super::{bigint_to_uint128, find_best_swap_amount, precision_scales, DECIMAL_FRACTIONAL};
+
+/// Number of assets in a PCL pair
+const N_COINS: u128 = 2;
+
+/// Maximum number of bisection steps when solving the invariant. Each step
+/// halves the range, so this covers any amounts that fit in the BigInts used.
+const MAX_BISECTION_ITERATIONS: usize = 256;
+
+/// The parameters of an Astroport concentrated liquidity pair, as returned in
+/// the `params` of its config. Only the fields needed here are read.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ConcentratedPoolParams {
+    /// Amplification coefficient
+    pub amp: Decimal,
+    /// Distance from the price scale at which the curve stops behaving like
+    /// a StableSwap curve
+    pub gamma: Decimal,
+    /// Fee when the pool is balanced
+    pub mid_fee: Decimal,
+    /// Fee when the pool is imbalanced
+    pub out_fee: Decimal,
+    /// How quickly the fee moves from `mid_fee` to `out_fee`
+    pub fee_gamma: Decimal,
+    /// Price of the second asset denominated in the first asset, that the
+    /// liquidity is concentrated around
+    pub price_scale: Decimal,
+}
+
+/// The parameters of a PCL pair as BigInts scaled by `DECIMAL_FRACTIONAL`
+struct Params {
+    ann: BigInt,
+    gamma: BigInt,
+    mid_fee: BigInt,
+    out_fee: BigInt,
+    fee_gamma: BigInt,
+    price_scale: BigInt,
+}
+
+impl From<&ConcentratedPoolParams> for Params {
+    fn from(params: &ConcentratedPoolParams) -> Self {
+        let to_bigint = |decimal: Decimal| BigInt::from(decimal.atomics().u128());
+        Self {
+            ann: N_COINS * N_COINS * &to_bigint(params.amp),
+            gamma: to_bigint(params.gamma),
+            mid_fee: to_bigint(params.mid_fee),
+            out_fee: to_bigint(params.out_fee),
+            fee_gamma: to_bigint(params.fee_gamma),
+            price_scale: to_bigint(params.price_scale),
+        }
+    }
+}
+
+impl Params {
+    /// Scales normalized amounts of the two assets into the units of the
+    /// invariant
+    fn scale(&self, amounts: &[BigInt; 2]) -> [BigInt; 2] {
+        [
+            amounts[0].clone(),
+            &amounts[1] * &self.price_scale / DECIMAL_FRACTIONAL,
+        ]
+    }
+
+    /// Evaluates the invariant for `d` and the scaled reserves, multiplied by
+    /// `DECIMAL_FRACTIONAL`. It is positive when `d` is too small.
+    fn invariant(&self, d: &BigInt, x: &[BigInt; 2]) -> BigInt {
+        let fractional = BigInt::from(DECIMAL_FRACTIONAL);
+        let k0 = 4u128 * &x[0] * &x[1] * &fractional / (d * d);
+        let denominator = &self.gamma + &fractional - &k0;
+        let k = &self.ann * &k0 * &self.gamma * &self.gamma
+            / (&fractional * &denominator * &denominator);
+
+        &k * d * (&x[0] + &x[1]) + &fractional * &x[0] * &x[1]
+            - &k * d * d
+            - &fractional * d * d / 4u128
+    }
+
+    /// Calculates the invariant D for the scaled reserves `x`
+    fn invariant_d(&self, x: &[BigInt; 2]) -> StdResult<BigInt> {
+        let zero = BigInt::from(0u128);
+        if x[0] <= zero || x[1] <= zero {
+            return Err(StdError::generic_err("No liquidity in pool"));
+        }
+
+        // D lies between the geometric and arithmetic means of the reserves
+        let one = BigInt::from(1u128);
+        let mut low = super::bigint_sqrt(4u128 * &x[0] * &x[1])?;
+        let mut high = &x[0] + &x[1];
+        for _ in 0..MAX_BISECTION_ITERATIONS {
+            if &high - &low <= one {
+                return Ok(low);
+            }
+            let mid = (&low + &high) / 2u128;
+            if self.invariant(&mid, x) > zero {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+
+        Err(StdError::generic_err("PCL invariant did not converge"))
+    }
+
+    /// Calculates the scaled reserve of one asset that keeps the invariant at
+    /// `d` when the scaled reserve of the other asset is `x`. Rounded up, in
+    /// the favor of the pool.
+    fn other_reserve(&self, x: &BigInt, d: &BigInt) -> StdResult<BigInt> {
+        let zero = BigInt::from(0u128);
+        let one = BigInt::from(1u128);
+        let mut low = one.clone();
+        let mut high = d.clone();
+        for _ in 0..MAX_BISECTION_ITERATIONS {
+            if &high - &low <= one {
+                return Ok(high);
+            }
+            let mid = (&low + &high) / 2u128;
+            if self.invariant(d, &[x.clone(), mid.clone()]) < zero {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+
+        Err(StdError::generic_err("PCL invariant did not converge"))
+    }
+
+    /// Returns the fee rate for the scaled reserves `x`, which moves from
+    /// `mid_fee` towards `out_fee` the further the reserves are from balanced
+    fn fee(&self, x: &[BigInt; 2]) -> BigInt {
+        let fractional = BigInt::from(DECIMAL_FRACTIONAL);
+        let sum = &x[0] + &x[1];
+        let k = 4u128 * &x[0] * &x[1] * &fractional / (&sum * &sum);
+        let f = &self.fee_gamma * &fractional / (&self.fee_gamma + &fractional - &k);
+        (&self.mid_fee * &f + &self.out_fee * (&fractional - &f)) / &fractional
+    }
+
+    /// Calculates how much will be returned from swapping `offer_amount` of
+    /// the asset at index `offer` into the other asset, in normalized amounts.
+    /// `d` is the invariant of the reserves, which is passed in so that it is
+    /// only calculated once when simulating many swaps.
+    fn swap(
+        &self,
+        reserves: &[BigInt; 2],
+        d: &BigInt,
+        offer: usize,
+        offer_amount: &BigInt,
+    ) -> StdResult<BigInt> {
+        let ask = 1 - offer;
+        let x = self.scale(reserves);
+
+        let mut offer_amounts = [BigInt::from(0u128), BigInt::from(0u128)];
+        offer_amounts[offer] = offer_amount.clone();
+        let mut x_after = x.clone();
+        x_after[offer] = &x[offer] + &self.scale(&offer_amounts)[offer];
+        x_after[ask] = self.other_reserve(&x_after[offer], d)?;
+
+        // Round down by one, in the favor of the pool
+        let zero = BigInt::from(0u128);
+        let return_amount = &x[ask] - &x_after[ask] - 1u128;
+        if return_amount <= zero {
+            return Ok(zero);
+        }
+        let return_amount =
+            &return_amount - &return_amount * self.fee(&x_after) / DECIMAL_FRACTIONAL;
+
+        // Scale the return amount back from the units of the invariant
+        if ask == 0 {
+            Ok(return_amount)
+        } else {
+            Ok(return_amount * DECIMAL_FRACTIONAL / &self.price_scale)
+        }
+    }
+
+    /// Calculates the share of the pool minted as LP tokens when providing
+    /// `amounts` to a pair with the given normalized `reserves`. The pair
+    /// charges a fee on the share for deposits that are not balanced at the
+    /// price scale. Returns the share as a `(numerator, denominator)`
+    /// fraction.
+    fn provide_share(
+        &self,
+        amounts: &[BigInt; 2],
+        reserves: &[BigInt; 2],
+    ) -> StdResult<(BigInt, BigInt)> {
+        let fractional = BigInt::from(DECIMAL_FRACTIONAL);
+        let d_before = self.invariant_d(&self.scale(reserves))?;
+        let x_after = self.scale(&[&reserves[0] + &amounts[0], &reserves[1] + &amounts[1]]);
+        let d_after = self.invariant_d(&x_after)?;
+
+        let deposits = self.scale(amounts);
+        let sum = &deposits[0] + &deposits[1];
+        let average = &sum / N_COINS;
+        let mut deviation = BigInt::from(0u128);
+        for deposit in deposits.iter() {
+            deviation = deviation
+                + if deposit > &average {
+                    deposit - &average
+                } else {
+                    &average - deposit
+                };
+        }
+        let fee = deviation * self.fee(&x_after) / (N_COINS * &sum);
+
+        Ok((
+            (d_after - &d_before) * (&fractional - &fee),
+            d_before * &fractional,
+        ))
+    }
+}
+
+/// For an Astroport concentrated liquidity pair, calculates how much of one
+/// asset we need to swap to the other before providing liquidity so that we
+/// get the most amount of LP tokens. The pair accepts liquidity in any ratio,
+/// but charges a fee on deposits that are not balanced at its price scale, so
+/// the swap amount is found by searching for the largest LP output, and may be
+/// zero when swapping costs more than that fee.
+///
+/// `precisions` are the decimals of the two assets, which the pair normalizes
+/// amounts to before using them in the invariant.
+///
+/// Returns `(offer_asset, return_asset): (Asset,Asset)` containing the amount
+/// and info of the asset we need to swap, and the asset that will be returned
+/// from the swap
+pub fn calc_pcl_balancing_swap(
+    assets: [Asset; 2],
+    reserves: [Uint128; 2],
+    precisions: [u8; 2],
+    params: &ConcentratedPoolParams,
+) -> StdResult<(Asset, Asset)> {
+    // Make sure there is liquidity in the pool
+    if reserves[0].is_zero() || reserves[1].is_zero() {
+        return Err(StdError::generic_err("No liquidity in pool"));
+    }
+
+    let scales = precision_scales(precisions);
+    let params = Params::from(params);
+    let balances = [
+        BigInt::from(assets[0].amount.u128()) * &scales[0],
+        BigInt::from(assets[1].amount.u128()) * &scales[1],
+    ];
+    let reserves = [
+        BigInt::from(reserves[0].u128()) * &scales[0],
+        BigInt::from(reserves[1].u128()) * &scales[1],
+    ];
+
+    // Offer the asset that we have more of relative to the pool reserves
+    let offer = if &balances[0] * &reserves[1] >= &balances[1] * &reserves[0] {
+        0
+    } else {
+        1
+    };
+    let ask = 1 - offer;
+    let d = params.invariant_d(&params.scale(&reserves))?;
+
+    // Returns the share of the pool minted when swapping `offer_amount` and
+    // then providing, together with the amount returned from the swap
+    let provide_share = |offer_amount: Uint128| -> StdResult<((BigInt, BigInt), Uint128)> {
+        let offer_int = BigInt::from(offer_amount.u128()) * &scales[offer];
+        let return_amount =
+            bigint_to_uint128(params.swap(&reserves, &d, offer, &offer_int)? / &scales[ask])?;
+        let return_int = BigInt::from(return_amount.u128()) * &scales[ask];
+
+        let mut amounts = balances.clone();
+        let mut new_reserves = reserves.clone();
+        amounts[offer] = &amounts[offer] - &offer_int;
+        amounts[ask] = &amounts[ask] + &return_int;
+        new_reserves[offer] = &new_reserves[offer] + &offer_int;
+        new_reserves[ask] = &new_reserves[ask] - &return_int;
+
+        let share = params.provide_share(&amounts, &new_reserves)?;
+        Ok((share, return_amount))
+    };
+
+    let (offer_amount, return_amount) = find_best_swap_amount(assets[offer].amount, provide_share)?;
+
+    Ok((
+        Asset {
+            amount: offer_amount,
+            info: assets[offer].info.clone(),
+        },
+        Asset {
+            amount: return_amount,
+            info: assets[ask].info.clone(),
+        },
+    ))
+}
+
+/// Calculates the amount of LP tokens minted when providing `amounts` to an
+/// Astroport concentrated liquidity pair with the given `reserves` and
+/// `total_share` of LP tokens, including the fee on unbalanced deposits. The
+/// amounts, reserves and precisions must be in the same order.
+pub fn calc_pcl_lp_out(
+    amounts: [Uint128; 2],
+    reserves: [Uint128; 2],
+    precisions: [u8; 2],
+    params: &ConcentratedPoolParams,
+    total_share: Uint128,
+) -> StdResult<Uint128> {
+    if reserves[0].is_zero() || reserves[1].is_zero() || total_share.is_zero() {
+        return Err(StdError::generic_err("No liquidity in pool"));
+    }
+
+    let scales = precision_scales(precisions);
+    let (numerator, denominator) = Params::from(params).provide_share(
+        &[
+            BigInt::from(amounts[0].u128()) * &scales[0],
+            BigInt::from(amounts[1].u128()) * &scales[1],
+        ],
+        &[
+            BigInt::from(reserves[0].u128()) * &scales[0],
+            BigInt::from(reserves[1].u128()) * &scales[1],
+        ],
+    )?;
+
+    bigint_to_uint128(BigInt::from(total_share.u128()) * numerator / denominator)
+}
+
+/// Calculates how much `offer_amount` of the asset at index `offer` is worth
+/// in the other asset at the price scale of an Astroport concentrated
+/// liquidity pair, before fees and price impact. The precisions must be in
+/// the order of the assets in the pair, which the price scale refers to.
+pub fn calc_pcl_spot_return(
+    offer_amount: Uint128,
+    offer: usize,
+    precisions: [u8; 2],
+    params: &ConcentratedPoolParams,
+) -> StdResult<Uint128> {
+    let scales = precision_scales(precisions);
+    let params = Params::from(params);
+    let offer_int = BigInt::from(offer_amount.u128()) * &scales[offer];
+
+    // The price scale is the price of the second asset in the first asset
+    let return_int = if offer == 0 {
+        offer_int * DECIMAL_FRACTIONAL / &params.price_scale
+    } else {
+        offer_int * &params.price_scale / DECIMAL_FRACTIONAL
+    };
+    bigint_to_uint128(return_int / &scales[1 - offer])
+}
+
+#[cfg(test)]
+mod test {
+    use cosmwasm_std::{Decimal, Uint128};
+    use cw_asset::Asset;
+    use cw_bigint::BigInt;
+
+    use super::{
+        calc_pcl_balancing_swap, calc_pcl_lp_out, calc_pcl_spot_return, ConcentratedPoolParams,
+        Params,
+    };
+    use crate::math::bigint_to_uint128;
+
+    fn params(price_scale: Decimal) -> ConcentratedPoolParams {
+        ConcentratedPoolParams {
+            amp: Decimal::from_ratio(10u128, 1u128),
+            gamma: Decimal::from_ratio(145u128, 1_000_000u128),
+            mid_fee: Decimal::from_ratio(26u128, 10_000u128),
+            out_fee: Decimal::from_ratio(45u128, 10_000u128),
+            fee_gamma: Decimal::from_ratio(23u128, 100_000u128),
+            price_scale,
+        }
+    }
+
+    #[test]
+    fn test_calc_pcl_balancing_swap() {
+        let total_share = Uint128::from(1_000_000_000_000u128);
+
+        // Assets balanced at the price scale should not be swapped
+        let (offer_asset, _) = calc_pcl_balancing_swap(
+            [
+                Asset::native("uatom", 1_000_000u128),
+                Asset::native("uosmo", 1_000_000u128),
+            ],
+            [1_000_000_000_000u128.into(), 1_000_000_000_000u128.into()],
+            [6, 6],
+            &params(Decimal::one()),
+        )
+        .unwrap();
+        assert_eq!(offer_asset.amount, Uint128::zero());
+
+        // A single sided deposit is partly swapped, which gives more LP tokens
+        // than providing it as it is. The second asset is worth half as much
+        // as the first, so the reserves are balanced at the price scale.
+        let assets = [
+            Asset::native("uatom", 0u128),
+            Asset::native("uosmo", 100_000_000_000u128),
+        ];
+        let reserves = [
+            Uint128::from(2_000_000_000_000u128),
+            Uint128::from(1_000_000_000_000u128),
+        ];
+        let params = params(Decimal::from_ratio(2u128, 1u128));
+        let (offer_asset, return_asset) =
+            calc_pcl_balancing_swap(assets.clone(), reserves, [6, 6], &params).unwrap();
+        assert_eq!(offer_asset.info, assets[1].info);
+        assert!(offer_asset.amount > Uint128::zero());
+        assert!(offer_asset.amount < assets[1].amount / Uint128::from(2u8));
+
+        let lp_out_without_swap = calc_pcl_lp_out(
+            [assets[0].amount, assets[1].amount],
+            reserves,
+            [6, 6],
+            &params,
+            total_share,
+        )
+        .unwrap();
+        let lp_out_with_swap = calc_pcl_lp_out(
+            [
+                assets[0].amount + return_asset.amount,
+                assets[1].amount - offer_asset.amount,
+            ],
+            [
+                reserves[0] - return_asset.amount,
+                reserves[1] + offer_asset.amount,
+            ],
+            [6, 6],
+            &params,
+            total_share,
+        )
+        .unwrap();
+        assert!(lp_out_with_swap > lp_out_without_swap);
+    }
+
+    #[test]
+    fn test_pcl_invariant_and_swap_reference_values() {
+        // The reference values are solved from the CryptoSwap equations in
+        // 100 digit decimal arithmetic, and rounded down. The pair rounds in
+        // its own favor, so swaps may return one less.
+        let params = Params::from(&params(Decimal::from_ratio(2u128, 1u128)));
+        let bigints = |amounts: [u128; 2]| [BigInt::from(amounts[0]), BigInt::from(amounts[1])];
+
+        // D is the sum of the scaled reserves when they are balanced
+        let reserves = bigints([2_000_000_000_000, 1_000_000_000_000]);
+        let d = params.invariant_d(&params.scale(&reserves)).unwrap();
+        assert_eq!(d, BigInt::from(4_000_000_000_000u128));
+
+        // and less than that otherwise
+        let imbalanced_reserves = bigints([1_000_000_000_000, 3_000_000_000_000]);
+        let imbalanced_d = params
+            .invariant_d(&params.scale(&imbalanced_reserves))
+            .unwrap();
+        assert_eq!(imbalanced_d, BigInt::from(4_926_375_313_147u128));
+
+        let test_cases = vec![
+            // Small swap, close to the price scale minus the fee
+            (0, 1_000_000u128, 498_699u128),
+            (0, 10_000_000_000u128, 4_985_649_662u128),
+            (1, 100_000_000_000u128, 184_969_917_896u128),
+            (1, 500_000_000_000u128, 672_134_970_021u128),
+            // Swap as large as the reserve of the offer asset
+            (0, 1_000_000_000_000u128, 336_067_485_010u128),
+        ];
+        for (offer, offer_amount, expected) in test_cases {
+            let return_amount = bigint_to_uint128(
+                params
+                    .swap(&reserves, &d, offer, &BigInt::from(offer_amount))
+                    .unwrap(),
+            )
+            .unwrap();
+            assert!(
+                return_amount.u128() <= expected && return_amount.u128() + 1 >= expected,
+                "swapping {} of asset {}: expected {}, got {}",
+                offer_amount,
+                offer,
+                expected,
+                return_amount
+            );
+        }
+    }
+
+    #[test]
+    fn test_calc_pcl_spot_return() {
+        // The second asset is worth two of the first
+        let params = params(Decimal::from_ratio(2u128, 1u128));
+
+        let spot_return =
+            calc_pcl_spot_return(Uint128::from(1_000u128), 0, [6, 6], &params).unwrap();
+        assert_eq!(spot_return, Uint128::from(500u128));
+        let spot_return =
+            calc_pcl_spot_return(Uint128::from(1_000u128), 1, [6, 6], &params).unwrap();
+        assert_eq!(spot_return, Uint128::from(2_000u128));
+
+        // Amounts are normalized to the precision of the other asset
+        let spot_return =
+            calc_pcl_spot_return(Uint128::from(1_000_000u128), 0, [6, 18], &params).unwrap();
+        assert_eq!(spot_return, Uint128::from(500_000_000_000_000_000u128));
+    }
+}
@@ -0,0 +1,34 @@
+//! Module containing the state migrations run by the `migrate` entry point
+
+use cosmwasm_std::{Addr, Storage};
+
+use crate::error::ContractError;
+use crate::state::{Config, CONFIG, OWNER};
+
+/// Storage layout of version 0.1.x of the contract
+mod v0_1 {
+    use cosmwasm_std::Addr;
+    use cw_storage_plus::Item;
+
+    pub const ASTROPORT_FACTORY: Item<Addr> = Item::new("astroport_factory");
+}
+
+/// Migrates the state from version 0.1.x, which stored only the factory
+/// address in its own item and had no owner, to the `Config` struct.
+pub fn migrate_from_v0_1(storage: &mut dyn Storage, owner: Addr) -> Result<(), ContractError> {
+    let astroport_factory = v0_1::ASTROPORT_FACTORY.load(storage)?;
+    v0_1::ASTROPORT_FACTORY.remove(storage);
+
+    CONFIG.save(
+        storage,
+        &Config {
+            astroport_factory,
+            guardian: None,
+            astroport_generator: None,
+            astroport_router: None,
+        },
+    )?;
+    OWNER.save(storage, &owner)?;
+
+    Ok(())
+}
@@ -1,11 +1,22 @@
+use astroport::factory::PairType as AstroPairType;
+use astroport::router::SwapOperation;
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{to_binary, Addr, Binary, CosmosMsg, Env, StdResult, Uint128, WasmMsg};
-use cw_asset::{AssetList, AssetListUnchecked};
+use cosmwasm_std::{to_binary, Addr, Binary, CosmosMsg, Decimal, Env, StdResult, Uint128, WasmMsg};
+use cw20::Cw20ReceiveMsg;
+use cw_asset::{
+    Asset, AssetInfo, AssetInfoUnchecked, AssetList, AssetListUnchecked, AssetUnchecked,
+};
 use cw_dex::astroport::AstroportPool;
 
+use crate::state::Config;
+
 #[cw_serde]
 pub struct InstantiateMsg {
+    pub owner: String,
     pub astroport_factory: String,
+    pub guardian: Option<String>,
+    pub astroport_generator: Option<String>,
+    pub astroport_router: Option<String>,
 }
 
 #[cw_serde]
@@ -15,20 +26,263 @@ pub enum ExecuteMsg {
         min_out: Uint128,
         pool: Binary,
         recipient: Option<String>,
+        /// Maximum spread allowed on the swap done to balance the assets,
+        /// relative to `belief_price` if given, or otherwise to the return
+        /// amount at the spot price of the pool before the swap.
+        max_spread: Option<Decimal>,
+        /// Price of the ask asset denominated in the offer asset that the
+        /// caller expects for the balancing swap.
+        belief_price: Option<Decimal>,
+        /// If given, the minimum amount of LP tokens to receive is the larger
+        /// of `min_out` and the simulated amount of LP tokens reduced by this
+        /// tolerance.
+        slippage_tolerance: Option<Decimal>,
+        /// Whether to stake the LP tokens in the Astroport generator on behalf
+        /// of the recipient instead of sending them. Defaults to false.
+        auto_stake: Option<bool>,
+        /// Routes for swapping assets that are not in the pool into pool
+        /// assets through the Astroport router. Assets without a route are
+        /// swapped through a pair with one of the pool assets registered in
+        /// the factory.
+        swap_routes: Option<Vec<SwapRoute>>,
+    },
+    /// Same as `BalancingProvideLiquidity`, but the pool is looked up in the
+    /// Astroport factory instead of being passed as a serialized
+    /// `AstroportPool`.
+    BalancingProvideLiquidityByPair {
+        assets: AssetListUnchecked,
+        min_out: Uint128,
+        pair: PairIdentifier,
+        recipient: Option<String>,
+        max_spread: Option<Decimal>,
+        belief_price: Option<Decimal>,
+        slippage_tolerance: Option<Decimal>,
+        auto_stake: Option<bool>,
+        swap_routes: Option<Vec<SwapRoute>>,
+    },
+    /// Creates an Astroport pair for the two `assets` through the factory,
+    /// unless one already exists, and provides the initial liquidity in the
+    /// ratio of the supplied assets. The pair must not have any liquidity
+    /// yet. The LP tokens are sent to `recipient`, or the caller if not given.
+    CreatePairAndProvideLiquidity {
+        assets: AssetListUnchecked,
+        pair_type: AstroPairType,
+        /// Parameters passed to the pair when it is instantiated, such as the
+        /// amplification of stable pairs
+        init_params: Option<Binary>,
+        min_out: Uint128,
+        recipient: Option<String>,
+    },
+    /// Receives a cw20 sent with `Cw20ExecuteMsg::Send` and handles the
+    /// `Cw20HookMsg` embedded in it.
+    Receive(Cw20ReceiveMsg),
+    /// Withdraws liquidity with the given LP tokens and swaps the withdrawn
+    /// assets into `ask_asset`. If `ask_asset` is not one of the pool's
+    /// assets, the withdrawn assets are swapped through the Astroport router.
+    BalancingWithdrawLiquidity {
+        lp_token: AssetUnchecked,
+        ask_asset: AssetInfoUnchecked,
+        min_out: Uint128,
+        pool: Binary,
+        recipient: Option<String>,
+        /// Routes for swapping the withdrawn pool assets into `ask_asset`
+        /// through the Astroport router. Pool assets without a route are
+        /// swapped in the pool itself if `ask_asset` is a pool asset, and
+        /// otherwise through a pair with `ask_asset` registered in the
+        /// factory.
+        swap_routes: Option<Vec<SwapRoute>>,
+    },
+    /// Provides `rewards` as liquidity to `pool`, and stakes the LP tokens
+    /// received in the generator on behalf of the caller. Nothing is claimed
+    /// here: the generator only pays rewards out to the staker, so the caller
+    /// claims them first, for example in the same transaction with the
+    /// messages returned by `LiquidityHelper::compound`. Native rewards must
+    /// be sent as funds and cw20 rewards approved first. Rewards that are not
+    /// pool assets are swapped through the router. Every swap fails if its
+    /// spread is larger than `max_spread`, which is measured against the pool
+    /// price at execution and therefore does not protect against a price moved
+    /// earlier in the block. Only `min_out` does, so it should be set from a
+    /// simulation. Fails if fewer than `min_out` LP tokens are staked.
+    Compound {
+        pool: Binary,
+        rewards: AssetListUnchecked,
+        min_out: Uint128,
+        max_spread: Decimal,
+        swap_routes: Option<Vec<SwapRoute>>,
+    },
+    /// Withdraws liquidity with the given LP tokens and swaps between the
+    /// withdrawn assets so that their value is split according to `weights`.
+    /// Pool assets without a weight get a weight of zero. `min_out` is checked
+    /// for each asset returned. Only supported for XYK pools.
+    BalancingWithdrawLiquidityToRatio {
+        lp_token: AssetUnchecked,
+        weights: Vec<AssetWeight>,
+        min_out: AssetListUnchecked,
+        pool: Binary,
+        recipient: Option<String>,
+    },
+    /// Withdraws liquidity with `amount` of the caller's LP tokens of `pool`,
+    /// and swaps the withdrawn assets into `ask_asset` in the same way as
+    /// `BalancingWithdrawLiquidity`. Nothing is unstaked here: the generator
+    /// only lets stakers withdraw their own deposits, so the caller unstakes
+    /// the LP tokens first, for example in the same transaction with the
+    /// messages returned by `LiquidityHelper::exit_position`. The LP tokens
+    /// must then be approved like for `BalancingWithdrawLiquidity`. Staked LP
+    /// tokens stay with the generator, so the staker can always unstake them
+    /// directly, also while this contract is paused.
+    ExitPosition {
+        pool: Binary,
+        amount: Uint128,
+        ask_asset: AssetInfoUnchecked,
+        min_out: Uint128,
+        recipient: Option<String>,
+        swap_routes: Option<Vec<SwapRoute>>,
+    },
+    /// Updates the config. Only callable by the owner. Fields that are not
+    /// given are left unchanged.
+    UpdateConfig {
+        astroport_factory: Option<String>,
+        guardian: Option<AddrUpdate>,
+        astroport_generator: Option<AddrUpdate>,
+        astroport_router: Option<AddrUpdate>,
     },
+    /// Pauses or unpauses all zap messages. The guardian can only pause,
+    /// while the owner can both pause and unpause.
+    SetPaused {
+        paused: bool,
+    },
+    /// Pauses or unpauses the zap messages for the pool with the given pair
+    /// address. The guardian can only pause, while the owner can both pause
+    /// and unpause.
+    SetPoolPaused {
+        pair_addr: String,
+        paused: bool,
+    },
+    /// Proposes a new owner, who must then claim the ownership. Only callable
+    /// by the owner.
+    ProposeNewOwner {
+        owner: String,
+    },
+    /// Removes the pending ownership proposal. Only callable by the owner.
+    DropOwnershipProposal {},
+    /// Accepts the ownership proposal. Only callable by the proposed owner.
+    ClaimOwnership {},
     Callback(CallbackMsg),
 }
 
+/// Messages that can be embedded in a `Cw20ExecuteMsg::Send` to this contract
+#[cw_serde]
+pub enum Cw20HookMsg {
+    /// Same as `ExecuteMsg::BalancingProvideLiquidity`, providing only the
+    /// sent cw20. `assets` may be empty or list the sent cw20, whose amount is
+    /// then ignored, and any other asset is rejected. To provide several
+    /// assets, use `ExecuteMsg::BalancingProvideLiquidity` with an allowance
+    /// instead. The recipient defaults to the sender of the cw20.
+    BalancingProvideLiquidity {
+        assets: AssetListUnchecked,
+        min_out: Uint128,
+        pool: Binary,
+        recipient: Option<String>,
+        max_spread: Option<Decimal>,
+        belief_price: Option<Decimal>,
+        slippage_tolerance: Option<Decimal>,
+        auto_stake: Option<bool>,
+        swap_routes: Option<Vec<SwapRoute>>,
+    },
+}
+
+/// Router swap operations for swapping an asset that is not in the pool into
+/// one of the pool assets
+#[cw_serde]
+pub struct SwapRoute {
+    pub offer_asset_info: AssetInfoUnchecked,
+    pub operations: Vec<SwapOperation>,
+    /// Minimum amount of the pool asset to receive from the whole route
+    pub minimum_receive: Option<Uint128>,
+}
+
+/// The share of the total value that should end up in an asset
+#[cw_serde]
+pub struct AssetWeight {
+    pub info: AssetInfoUnchecked,
+    pub weight: Decimal,
+}
+
+/// Update of an optional address in the config
+#[cw_serde]
+pub enum AddrUpdate {
+    /// Sets the address
+    Set(String),
+    /// Removes the address
+    Clear {},
+}
+
+/// Identifies an Astroport pair, either by all of its assets or by its
+/// contract address.
+#[cw_serde]
+pub enum PairIdentifier {
+    AssetInfos(Vec<AssetInfoUnchecked>),
+    PairAddr(String),
+}
+
+/// Messages the contract sends to itself. The callbacks of providing liquidity
+/// take the pool serialized, like the `pool` of `ExecuteMsg`, since they are
+/// sent for any `BalancingPool`.
 #[cw_serde]
 pub enum CallbackMsg {
     ProvideLiquidity {
         assets: AssetList,
         min_out: Uint128,
-        pool: AstroportPool,
+        pool: Binary,
+    },
+    BootstrapPool {
+        asset_infos: [AssetInfo; 2],
+        assets: AssetList,
+        min_out: Uint128,
+        recipient: Addr,
+    },
+    ProvideSwappedAssets {
+        pool: Binary,
+        balances_before: AssetList,
+        min_out: Uint128,
+        max_spread: Option<Decimal>,
+        belief_price: Option<Decimal>,
+        slippage_tolerance: Option<Decimal>,
     },
     ReturnLpTokens {
+        pool: Binary,
+        balance_before: Uint128,
+        recipient: Addr,
+        auto_stake: bool,
+    },
+    RefundLeftovers {
+        balances_before: AssetList,
+        recipient: Addr,
+    },
+    SwapWithdrawnAssets {
+        pool: AstroportPool,
+        ask_asset_info: AssetInfo,
+        balances_before: AssetList,
+        swap_routes: Vec<SwapRoute>,
+        min_out: Uint128,
+        recipient: Addr,
+    },
+    SwapWithdrawnAssetsToRatio {
         pool: AstroportPool,
+        balances_before: AssetList,
+        weights: [Decimal; 2],
+        min_out: AssetList,
+        recipient: Addr,
+    },
+    ReturnAssets {
+        balances_before: AssetList,
+        min_out: AssetList,
+        recipient: Addr,
+    },
+    ReturnAsset {
+        asset_info: AssetInfo,
         balance_before: Uint128,
+        min_out: Uint128,
         recipient: Addr,
     },
 }
@@ -48,7 +302,81 @@ impl CallbackMsg {
 pub enum QueryMsg {
     #[returns(Addr)]
     AstroportFactory {},
+    #[returns(ConfigResponse)]
+    Config {},
+    #[returns(PauseInfoResponse)]
+    PauseInfo {},
+    /// Simulates a `BalancingWithdrawLiquidityToRatio` with the given LP
+    /// tokens and weights
+    #[returns(SimulateWithdrawLiquidityToRatioResponse)]
+    SimulateWithdrawLiquidityToRatio {
+        lp_token: AssetUnchecked,
+        weights: Vec<AssetWeight>,
+        pool: Binary,
+    },
+    /// Returns the amount of `lp_token` that `user` has staked in the
+    /// generator
+    #[returns(StakingPositionResponse)]
+    StakingPosition {
+        lp_token: AssetInfoUnchecked,
+        user: String,
+    },
+    /// Simulates a `BalancingProvideLiquidity` with the given assets and pool.
+    /// For stable pools with more than two assets, `provide_assets` in the
+    /// response contains all pool assets.
+    #[returns(SimulateBalancingProvideLiquidityResponse)]
+    SimulateBalancingProvideLiquidity {
+        assets: AssetListUnchecked,
+        pool: Binary,
+    },
+}
+
+#[cw_serde]
+pub struct ConfigResponse {
+    pub config: Config,
+    pub owner: Addr,
+    pub proposed_owner: Option<Addr>,
 }
 
 #[cw_serde]
-pub enum MigrateMsg {}
+pub struct PauseInfoResponse {
+    pub paused: bool,
+    pub paused_pools: Vec<Addr>,
+}
+
+#[cw_serde]
+pub struct StakingPositionResponse {
+    pub lp_amount: Uint128,
+}
+
+#[cw_serde]
+pub struct SimulateBalancingProvideLiquidityResponse {
+    /// The asset offered in the balancing swap, if a swap is needed
+    pub offer_asset: Option<Asset>,
+    /// The asset expected to be returned from the balancing swap
+    pub return_asset: Option<Asset>,
+    /// The assets that will be provided as liquidity after the swap
+    pub provide_assets: AssetList,
+    /// The expected amount of LP tokens minted
+    pub lp_tokens: Uint128,
+    /// The assets expected to be left over after providing liquidity
+    pub leftover_assets: AssetList,
+}
+
+#[cw_serde]
+pub struct SimulateWithdrawLiquidityToRatioResponse {
+    /// The assets withdrawn from the pool
+    pub withdrawn_assets: AssetList,
+    /// The asset offered in the swap, if a swap is needed
+    pub offer_asset: Option<Asset>,
+    /// The asset expected to be returned from the swap
+    pub return_asset: Option<Asset>,
+    /// The assets expected to be returned after the swap
+    pub return_assets: AssetList,
+}
+
+#[cw_serde]
+pub struct MigrateMsg {
+    /// The owner to set when migrating from a version without an owner
+    pub owner: Option<String>,
+}
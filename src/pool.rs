@@ -0,0 +1,370 @@
+//! Module containing helpers for querying and validating Astroport pools
+
+use astroport::asset::{Asset as AstroAsset, AssetInfo as AstroAssetInfo};
+use astroport::factory::{ExecuteMsg as FactoryExecuteMsg, PairType as AstroPairType};
+use astroport::pair::{
+    ConfigResponse as PairConfigResponse, QueryMsg as PairQueryMsg, SimulationResponse,
+    StablePoolConfig,
+};
+use astroport::querier::query_token_precision;
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    from_binary, to_binary, Addr, Binary, CosmosMsg, Decimal, Deps, QuerierWrapper, StdError,
+    StdResult, Uint128, WasmMsg,
+};
+use cw_asset::{Asset, AssetInfo};
+use cw_dex::astroport::msg::PairType;
+use cw_dex::astroport::AstroportPool;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::error::ContractError;
+use crate::math::pcl::ConcentratedPoolParams;
+use crate::msg::PairIdentifier;
+use crate::state::CONFIG;
+
+/// Name of the custom pair type of Astroport concentrated liquidity pairs
+pub const CONCENTRATED_PAIR_TYPE: &str = "concentrated";
+
+/// Converts a `cw_asset::AssetInfo` into the `AssetInfo` type used in
+/// Astroport messages
+pub fn to_astro_asset_info(info: &AssetInfo) -> AstroAssetInfo {
+    match info {
+        AssetInfo::Native(denom) => AstroAssetInfo::NativeToken {
+            denom: denom.clone(),
+        },
+        AssetInfo::Cw20(contract_addr) => AstroAssetInfo::Token {
+            contract_addr: contract_addr.clone(),
+        },
+    }
+}
+
+/// Converts an Astroport `AssetInfo` into a `cw_asset::AssetInfo`
+pub fn to_cw_asset_info(info: &AstroAssetInfo) -> AssetInfo {
+    match info {
+        AstroAssetInfo::NativeToken { denom } => AssetInfo::Native(denom.clone()),
+        AstroAssetInfo::Token { contract_addr } => AssetInfo::Cw20(contract_addr.clone()),
+    }
+}
+
+/// Converts the `PairType` returned by the Astroport factory into the
+/// `PairType` used by `AstroportPool`
+pub fn to_dex_pair_type(pair_type: &AstroPairType) -> PairType {
+    match pair_type {
+        AstroPairType::Xyk {} => PairType::Xyk {},
+        AstroPairType::Stable {} => PairType::Stable {},
+        AstroPairType::Custom(name) => PairType::Custom(name.clone()),
+    }
+}
+
+/// Pair query of the Astroport factory for any number of assets. The factory
+/// message of the pinned Astroport version only takes two assets, while pairs
+/// with more assets are registered under all of them.
+#[cw_serde]
+enum FactoryPairQueryMsg {
+    Pair { asset_infos: Vec<AstroAssetInfo> },
+}
+
+/// Info of an Astroport pair, as returned by the factory and by the pair
+/// itself. Unlike the `PairInfo` of the pinned Astroport version, this also
+/// parses pairs with more than two assets.
+#[derive(Deserialize)]
+pub struct PairInfo {
+    pub asset_infos: Vec<AstroAssetInfo>,
+    pub contract_addr: Addr,
+    pub liquidity_token: Addr,
+    pub pair_type: AstroPairType,
+}
+
+/// Queries the Astroport factory for the pair registered for the given assets
+pub fn query_pair_info(
+    querier: &QuerierWrapper,
+    factory: &Addr,
+    asset_infos: &[AssetInfo],
+) -> StdResult<PairInfo> {
+    querier.query_wasm_smart(
+        factory.to_string(),
+        &FactoryPairQueryMsg::Pair {
+            asset_infos: asset_infos.iter().map(to_astro_asset_info).collect(),
+        },
+    )
+}
+
+/// Queries the Astroport factory for the pair registered for the given assets,
+/// returning `None` if the factory has no such pair. Any other error, such as
+/// a failing query, is returned.
+pub fn query_registered_pair(
+    querier: &QuerierWrapper,
+    factory: &Addr,
+    asset_infos: &[AssetInfo],
+) -> StdResult<Option<PairInfo>> {
+    match query_pair_info(querier, factory, asset_infos) {
+        Ok(pair_info) => Ok(Some(pair_info)),
+        Err(err) if is_pair_not_found(&err) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns whether `err` is the error of a factory pair query for assets
+/// that have no pair registered. The factory fails with a `NotFound` error
+/// when loading the pair, which reaches us as a contract error message.
+fn is_pair_not_found(err: &StdError) -> bool {
+    match err {
+        StdError::NotFound { .. } => true,
+        StdError::GenericErr { msg } => {
+            msg.contains("Querier contract error") && msg.contains("not found")
+        }
+        _ => false,
+    }
+}
+
+/// Queries the config of a pair and parses the pair type specific `params`
+/// in it
+fn query_pair_params<T: DeserializeOwned>(
+    querier: &QuerierWrapper,
+    pool: &AstroportPool,
+) -> StdResult<T> {
+    let config: PairConfigResponse =
+        querier.query_wasm_smart(pool.pair_addr.to_string(), &PairQueryMsg::Config {})?;
+    from_binary(
+        &config
+            .params
+            .ok_or_else(|| StdError::generic_err("Pair has no params"))?,
+    )
+}
+
+/// Queries the precisions of the given assets, which pairs normalize amounts
+/// to. The precisions are returned in the same order as `asset_infos`.
+fn query_precisions(querier: &QuerierWrapper, asset_infos: &[AssetInfo]) -> StdResult<Vec<u8>> {
+    asset_infos
+        .iter()
+        .map(|info| query_token_precision(querier, &to_astro_asset_info(info)))
+        .collect()
+}
+
+/// Queries the amplification of a stable pair and the precisions of its
+/// assets, in the same order as `asset_infos`.
+pub fn query_stable_pool_params(
+    querier: &QuerierWrapper,
+    pool: &AstroportPool,
+    asset_infos: &[AssetInfo],
+) -> StdResult<(Decimal, Vec<u8>)> {
+    let params: StablePoolConfig = query_pair_params(querier, pool)?;
+    Ok((params.amp, query_precisions(querier, asset_infos)?))
+}
+
+/// Queries the parameters of a concentrated liquidity pair, including its
+/// current price scale, and the precisions of its assets, in the same order as
+/// `asset_infos`.
+pub fn query_concentrated_pool_params(
+    querier: &QuerierWrapper,
+    pool: &AstroportPool,
+    asset_infos: &[AssetInfo],
+) -> StdResult<(ConcentratedPoolParams, Vec<u8>)> {
+    let params: ConcentratedPoolParams = query_pair_params(querier, pool)?;
+    Ok((params, query_precisions(querier, asset_infos)?))
+}
+
+/// Queries the pair of `pool` for the amount returned when swapping
+/// `offer_asset`, after commission
+pub fn query_simulation(
+    querier: &QuerierWrapper,
+    pool: &AstroportPool,
+    offer_asset: &Asset,
+) -> StdResult<Uint128> {
+    let res: SimulationResponse = querier.query_wasm_smart(
+        pool.pair_addr.to_string(),
+        &PairQueryMsg::Simulation {
+            offer_asset: AstroAsset {
+                info: to_astro_asset_info(&offer_asset.info),
+                amount: offer_asset.amount,
+            },
+        },
+    )?;
+    Ok(res.return_amount)
+}
+
+/// Returns whether `pair_type` is the custom pair type of Astroport
+/// concentrated liquidity pairs
+pub fn is_concentrated(pair_type: &PairType) -> bool {
+    matches!(pair_type, PairType::Custom(name) if name == CONCENTRATED_PAIR_TYPE)
+}
+
+/// Asserts that the pair address, LP token and pair type of `pool` all match
+/// the pair registered in the Astroport factory for the pool's assets.
+pub fn assert_pool_registered(deps: Deps, pool: &AstroportPool) -> Result<(), ContractError> {
+    let pool_res = pool.query_pool_info(&deps.querier)?;
+    let asset_infos: Vec<AssetInfo> = pool_res
+        .assets
+        .iter()
+        .map(|asset| Asset::from(asset).info)
+        .collect();
+
+    let factory = CONFIG.load(deps.storage)?.astroport_factory;
+    let pair_info = query_pair_info(&deps.querier, &factory, &asset_infos)?;
+
+    if pair_info.contract_addr != pool.pair_addr
+        || pair_info.liquidity_token != pool.lp_token_addr
+        || to_dex_pair_type(&pair_info.pair_type) != pool.pair_type
+    {
+        return Err(ContractError::PoolNotRegistered {
+            pair_addr: pool.pair_addr.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Looks up the pair identified by `pair` in the Astroport factory and returns
+/// the corresponding `AstroportPool`.
+pub fn resolve_pool(deps: Deps, pair: PairIdentifier) -> Result<AstroportPool, ContractError> {
+    let factory = CONFIG.load(deps.storage)?.astroport_factory;
+
+    let pair_info = match pair {
+        PairIdentifier::AssetInfos(asset_infos) => {
+            let asset_infos = asset_infos
+                .iter()
+                .map(|info| info.check(deps.api))
+                .collect::<StdResult<Vec<_>>>()?;
+            query_pair_info(&deps.querier, &factory, &asset_infos)?
+        }
+        PairIdentifier::PairAddr(pair_addr) => {
+            let pair_addr = deps.api.addr_validate(&pair_addr)?;
+
+            // Query the pair for its assets, and then make sure that the
+            // factory has the same pair registered for those assets
+            let pair_info: PairInfo = deps
+                .querier
+                .query_wasm_smart(pair_addr.to_string(), &PairQueryMsg::Pair {})?;
+            let asset_infos: Vec<AssetInfo> =
+                pair_info.asset_infos.iter().map(to_cw_asset_info).collect();
+            let pair_info = query_pair_info(&deps.querier, &factory, &asset_infos)?;
+            if pair_info.contract_addr != pair_addr {
+                return Err(ContractError::PoolNotRegistered {
+                    pair_addr: pair_addr.to_string(),
+                });
+            }
+            pair_info
+        }
+    };
+
+    Ok(to_astroport_pool(pair_info))
+}
+
+/// Finds a pair registered in the Astroport factory that swaps `offer_info`
+/// into one of `ask_infos`, trying them in order. Returns the pool and the
+/// ask asset of the first pair found.
+pub fn find_swap_pool(
+    deps: Deps,
+    offer_info: &AssetInfo,
+    ask_infos: &[AssetInfo],
+) -> Result<(AstroportPool, AssetInfo), ContractError> {
+    let factory = CONFIG.load(deps.storage)?.astroport_factory;
+
+    for ask_info in ask_infos {
+        let asset_infos = [offer_info.clone(), ask_info.clone()];
+        if let Some(pair_info) = query_registered_pair(&deps.querier, &factory, &asset_infos)? {
+            return Ok((to_astroport_pool(pair_info), ask_info.clone()));
+        }
+    }
+
+    Err(ContractError::NoSwapRoute {
+        offer_asset: offer_info.to_string(),
+    })
+}
+
+/// Returns a message creating an Astroport pair for `asset_infos` through the
+/// factory
+pub fn create_pair_msg(
+    factory: &Addr,
+    pair_type: AstroPairType,
+    asset_infos: &[AssetInfo; 2],
+    init_params: Option<Binary>,
+) -> StdResult<CosmosMsg> {
+    Ok(WasmMsg::Execute {
+        contract_addr: factory.to_string(),
+        msg: to_binary(&FactoryExecuteMsg::CreatePair {
+            pair_type,
+            asset_infos: [
+                to_astro_asset_info(&asset_infos[0]),
+                to_astro_asset_info(&asset_infos[1]),
+            ],
+            init_params,
+        })?,
+        funds: vec![],
+    }
+    .into())
+}
+
+/// Converts the `PairInfo` returned by Astroport into an `AstroportPool`
+pub fn to_astroport_pool(pair_info: PairInfo) -> AstroportPool {
+    AstroportPool {
+        pair_addr: pair_info.contract_addr,
+        lp_token_addr: pair_info.liquidity_token,
+        pair_type: to_dex_pair_type(&pair_info.pair_type),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cosmwasm_std::testing::{mock_dependencies, MockQuerier};
+    use cosmwasm_std::{
+        Addr, Binary, ContractResult, QuerierWrapper, SystemError, SystemResult, WasmQuery,
+    };
+    use cw_asset::AssetInfo;
+
+    use super::query_registered_pair;
+
+    #[test]
+    fn test_query_registered_pair() {
+        let mut deps = mock_dependencies();
+        let factory = Addr::unchecked("factory");
+        let asset_infos = [AssetInfo::native("uosmo"), AssetInfo::native("uatom")];
+
+        let set_factory_response = |querier: &mut MockQuerier, found: bool| {
+            querier.update_wasm(move |query| match query {
+                WasmQuery::Smart { contract_addr, .. } if contract_addr == "factory" => {
+                    if found {
+                        SystemResult::Ok(ContractResult::Ok(Binary::from(
+                            br#"{
+                                "asset_infos": [
+                                    { "native_token": { "denom": "uosmo" } },
+                                    { "native_token": { "denom": "uatom" } }
+                                ],
+                                "contract_addr": "pair",
+                                "liquidity_token": "lp_token",
+                                "pair_type": { "xyk": {} }
+                            }"#
+                            .as_ref(),
+                        )))
+                    } else {
+                        SystemResult::Ok(ContractResult::Err(
+                            "cosmwasm_std::addr::Addr not found".to_string(),
+                        ))
+                    }
+                }
+                _ => SystemResult::Err(SystemError::NoSuchContract {
+                    addr: "other".to_string(),
+                }),
+            });
+        };
+
+        // The factory has no pair for the assets
+        set_factory_response(&mut deps.querier, false);
+        let querier = QuerierWrapper::new(&deps.querier);
+        assert!(query_registered_pair(&querier, &factory, &asset_infos)
+            .unwrap()
+            .is_none());
+
+        set_factory_response(&mut deps.querier, true);
+        let querier = QuerierWrapper::new(&deps.querier);
+        let pair_info = query_registered_pair(&querier, &factory, &asset_infos)
+            .unwrap()
+            .unwrap();
+        assert_eq!(pair_info.contract_addr, Addr::unchecked("pair"));
+
+        // Other errors, such as a missing factory, are not treated as no pair
+        let querier = QuerierWrapper::new(&deps.querier);
+        query_registered_pair(&querier, &Addr::unchecked("other"), &asset_infos).unwrap_err();
+    }
+}
@@ -0,0 +1,370 @@
+//! Module containing helpers for swapping through the Astroport router
+
+use astroport::asset::AssetInfo as AstroAssetInfo;
+use astroport::router::{
+    Cw20HookMsg as RouterCw20HookMsg, ExecuteMsg as RouterExecuteMsg, SwapOperation,
+};
+use cosmwasm_std::{to_binary, Addr, Coin, CosmosMsg, Decimal, Deps, StdResult, Uint128, WasmMsg};
+use cw20::Cw20ExecuteMsg;
+use cw_asset::{Asset, AssetInfo};
+
+use crate::error::ContractError;
+use crate::msg::SwapRoute;
+use crate::pool::{find_swap_pool, to_astro_asset_info};
+
+/// Returns the offer and ask asset infos of a router swap operation
+fn operation_asset_infos(operation: &SwapOperation) -> (AstroAssetInfo, AstroAssetInfo) {
+    match operation {
+        SwapOperation::NativeSwap {
+            offer_denom,
+            ask_denom,
+        } => (
+            AstroAssetInfo::NativeToken {
+                denom: offer_denom.clone(),
+            },
+            AstroAssetInfo::NativeToken {
+                denom: ask_denom.clone(),
+            },
+        ),
+        SwapOperation::AstroSwap {
+            offer_asset_info,
+            ask_asset_info,
+        } => (offer_asset_info.clone(), ask_asset_info.clone()),
+    }
+}
+
+/// Asserts that `operations` is a connected route from `offer_info` into one
+/// of `ask_infos`, and returns the asset that the route ends in.
+pub fn validate_operations(
+    offer_info: &AssetInfo,
+    operations: &[SwapOperation],
+    ask_infos: &[AssetInfo],
+) -> Result<AssetInfo, ContractError> {
+    let invalid_route = || ContractError::InvalidSwapRoute {
+        offer_asset: offer_info.to_string(),
+    };
+
+    let mut current = to_astro_asset_info(offer_info);
+    for operation in operations {
+        let (offer, ask) = operation_asset_infos(operation);
+        if offer != current {
+            return Err(invalid_route());
+        }
+        current = ask;
+    }
+
+    ask_infos
+        .iter()
+        .find(|info| to_astro_asset_info(info) == current)
+        .cloned()
+        .ok_or_else(invalid_route)
+}
+
+/// Returns the swap operations and minimum receive for swapping `offer_info`
+/// into one of `ask_infos`. Uses the route in `routes` for `offer_info` if
+/// there is one, and otherwise a direct pair found in the Astroport factory.
+pub fn find_route(
+    deps: Deps,
+    offer_info: &AssetInfo,
+    ask_infos: &[AssetInfo],
+    routes: &[SwapRoute],
+) -> Result<(Vec<SwapOperation>, Option<Uint128>), ContractError> {
+    for route in routes {
+        if &route.offer_asset_info.check(deps.api)? == offer_info {
+            validate_operations(offer_info, &route.operations, ask_infos)?;
+            return Ok((route.operations.clone(), route.minimum_receive));
+        }
+    }
+
+    let (_, ask_info) = find_swap_pool(deps, offer_info, ask_infos)?;
+    Ok((
+        vec![SwapOperation::AstroSwap {
+            offer_asset_info: to_astro_asset_info(offer_info),
+            ask_asset_info: to_astro_asset_info(&ask_info),
+        }],
+        None,
+    ))
+}
+
+/// Returns the routes for swapping each of `offer_infos` into `ask_info`, using
+/// the route in `routes` where one is given and otherwise a direct pair found
+/// in the Astroport factory. If `ask_info` is itself one of `offer_infos`, the
+/// assets without a given route are left out, so that they can be swapped
+/// directly in their pool instead.
+pub fn resolve_routes(
+    deps: Deps,
+    offer_infos: &[AssetInfo],
+    ask_info: &AssetInfo,
+    routes: &[SwapRoute],
+) -> Result<Vec<SwapRoute>, ContractError> {
+    let route_infos = routes
+        .iter()
+        .map(|route| route.offer_asset_info.check(deps.api))
+        .collect::<StdResult<Vec<_>>>()?;
+    let ask_in_offers = offer_infos.contains(ask_info);
+
+    let mut resolved = vec![];
+    for offer_info in offer_infos {
+        if offer_info == ask_info || (ask_in_offers && !route_infos.contains(offer_info)) {
+            continue;
+        }
+        let (operations, minimum_receive) =
+            find_route(deps, offer_info, std::slice::from_ref(ask_info), routes)?;
+        resolved.push(SwapRoute {
+            offer_asset_info: offer_info.clone().into(),
+            operations,
+            minimum_receive,
+        });
+    }
+
+    Ok(resolved)
+}
+
+/// Returns a message swapping `offer_asset` through the router with the given
+/// operations. `max_spread` is checked by the pair on every hop, while
+/// `minimum_receive` is checked by the router on the final amount.
+pub fn execute_swap_operations_msg(
+    router: &Addr,
+    offer_asset: &Asset,
+    operations: Vec<SwapOperation>,
+    minimum_receive: Option<Uint128>,
+    max_spread: Option<Decimal>,
+) -> StdResult<CosmosMsg> {
+    let msg = match &offer_asset.info {
+        AssetInfo::Native(denom) => WasmMsg::Execute {
+            contract_addr: router.to_string(),
+            msg: to_binary(&RouterExecuteMsg::ExecuteSwapOperations {
+                operations,
+                minimum_receive,
+                to: None,
+                max_spread,
+            })?,
+            funds: vec![Coin {
+                denom: denom.clone(),
+                amount: offer_asset.amount,
+            }],
+        },
+        AssetInfo::Cw20(contract_addr) => WasmMsg::Execute {
+            contract_addr: contract_addr.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: router.to_string(),
+                amount: offer_asset.amount,
+                msg: to_binary(&RouterCw20HookMsg::ExecuteSwapOperations {
+                    operations,
+                    minimum_receive,
+                    to: None,
+                    max_spread,
+                })?,
+            })?,
+            funds: vec![],
+        },
+    };
+    Ok(msg.into())
+}
+
+#[cfg(test)]
+mod test {
+    use astroport::asset::AssetInfo as AstroAssetInfo;
+    use astroport::router::SwapOperation;
+    use cosmwasm_std::testing::mock_dependencies;
+    use cosmwasm_std::{
+        from_binary, Addr, Binary, ContractResult, SystemError, SystemResult, Uint128, WasmQuery,
+    };
+    use cw_asset::AssetInfo;
+    use serde::Deserialize;
+
+    use super::{find_route, validate_operations};
+    use crate::msg::SwapRoute;
+    use crate::state::{Config, CONFIG};
+    use crate::ContractError;
+
+    /// The pair query of the Astroport factory
+    #[derive(Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum FactoryQuery {
+        Pair { asset_infos: Vec<AstroAssetInfo> },
+    }
+
+    fn astro_swap(offer: AstroAssetInfo, ask: &str) -> SwapOperation {
+        SwapOperation::AstroSwap {
+            offer_asset_info: offer,
+            ask_asset_info: AstroAssetInfo::NativeToken {
+                denom: ask.to_string(),
+            },
+        }
+    }
+
+    fn native(denom: &str) -> AstroAssetInfo {
+        AstroAssetInfo::NativeToken {
+            denom: denom.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_validate_operations() {
+        let astro = AssetInfo::Cw20(Addr::unchecked("astro"));
+        let pool_asset_infos = vec![
+            AssetInfo::Native("uluna".to_string()),
+            AssetInfo::Native("uusdc".to_string()),
+        ];
+        let astro_to_uatom = SwapOperation::AstroSwap {
+            offer_asset_info: AstroAssetInfo::Token {
+                contract_addr: Addr::unchecked("astro"),
+            },
+            ask_asset_info: AstroAssetInfo::NativeToken {
+                denom: "uatom".to_string(),
+            },
+        };
+        let uatom_to_uusdc = SwapOperation::AstroSwap {
+            offer_asset_info: AstroAssetInfo::NativeToken {
+                denom: "uatom".to_string(),
+            },
+            ask_asset_info: AstroAssetInfo::NativeToken {
+                denom: "uusdc".to_string(),
+            },
+        };
+
+        let ask_info = validate_operations(
+            &astro,
+            &[astro_to_uatom.clone(), uatom_to_uusdc.clone()],
+            &pool_asset_infos,
+        )
+        .unwrap();
+        assert_eq!(ask_info, AssetInfo::Native("uusdc".to_string()));
+
+        // Route does not end in a pool asset
+        let err = validate_operations(&astro, &[astro_to_uatom], &pool_asset_infos).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidSwapRoute { .. }));
+
+        // Route does not start with the offer asset
+        let err =
+            validate_operations(&astro, &[uatom_to_uusdc.clone()], &pool_asset_infos).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidSwapRoute { .. }));
+
+        // Hops that do not connect
+        let astro_to_uosmo = astro_swap(
+            AstroAssetInfo::Token {
+                contract_addr: Addr::unchecked("astro"),
+            },
+            "uosmo",
+        );
+        let err = validate_operations(&astro, &[astro_to_uosmo, uatom_to_uusdc], &pool_asset_infos)
+            .unwrap_err();
+        assert!(matches!(err, ContractError::InvalidSwapRoute { .. }));
+
+        // Native swaps can be part of a route
+        let ask_info = validate_operations(
+            &AssetInfo::Native("uatom".to_string()),
+            &[
+                SwapOperation::NativeSwap {
+                    offer_denom: "uatom".to_string(),
+                    ask_denom: "uosmo".to_string(),
+                },
+                astro_swap(native("uosmo"), "uluna"),
+            ],
+            &pool_asset_infos,
+        )
+        .unwrap();
+        assert_eq!(ask_info, AssetInfo::Native("uluna".to_string()));
+
+        // No operations at all only work for an asset that is already a pool
+        // asset
+        let err = validate_operations(&astro, &[], &pool_asset_infos).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidSwapRoute { .. }));
+    }
+
+    #[test]
+    fn test_find_route() {
+        let mut deps = mock_dependencies();
+        CONFIG
+            .save(
+                deps.as_mut().storage,
+                &Config {
+                    astroport_factory: Addr::unchecked("factory"),
+                    guardian: None,
+                    astroport_generator: None,
+                    astroport_router: Some(Addr::unchecked("router")),
+                },
+            )
+            .unwrap();
+
+        // The factory only has an astro-uluna pair
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { contract_addr, msg } if contract_addr == "factory" => {
+                let FactoryQuery::Pair { asset_infos } = from_binary(msg).unwrap();
+                let astro = AstroAssetInfo::Token {
+                    contract_addr: Addr::unchecked("astro"),
+                };
+                if asset_infos.contains(&astro) && asset_infos.contains(&native("uluna")) {
+                    SystemResult::Ok(ContractResult::Ok(Binary::from(
+                        br#"{
+                            "asset_infos": [
+                                { "token": { "contract_addr": "astro" } },
+                                { "native_token": { "denom": "uluna" } }
+                            ],
+                            "contract_addr": "astro_uluna_pair",
+                            "liquidity_token": "astro_uluna_lp_token",
+                            "pair_type": { "xyk": {} }
+                        }"#
+                        .as_ref(),
+                    )))
+                } else {
+                    SystemResult::Ok(ContractResult::Err(
+                        "cosmwasm_std::addr::Addr not found".to_string(),
+                    ))
+                }
+            }
+            _ => SystemResult::Err(SystemError::NoSuchContract {
+                addr: "other".to_string(),
+            }),
+        });
+
+        let astro = AssetInfo::Cw20(Addr::unchecked("astro"));
+        let astro_astro = AstroAssetInfo::Token {
+            contract_addr: Addr::unchecked("astro"),
+        };
+        let pool_asset_infos = vec![
+            AssetInfo::Native("uusdc".to_string()),
+            AssetInfo::Native("uluna".to_string()),
+        ];
+
+        // A given multi-hop route is used as it is
+        let operations = vec![
+            astro_swap(astro_astro.clone(), "uatom"),
+            astro_swap(native("uatom"), "uusdc"),
+        ];
+        let routes = vec![SwapRoute {
+            offer_asset_info: astro.clone().into(),
+            operations: operations.clone(),
+            minimum_receive: Some(Uint128::from(100u128)),
+        }];
+        let route = find_route(deps.as_ref(), &astro, &pool_asset_infos, &routes).unwrap();
+        assert_eq!(route, (operations, Some(Uint128::from(100u128))));
+
+        // A given route that does not end in a pool asset is rejected
+        let routes = vec![SwapRoute {
+            offer_asset_info: astro.clone().into(),
+            operations: vec![astro_swap(astro_astro.clone(), "uatom")],
+            minimum_receive: None,
+        }];
+        let err = find_route(deps.as_ref(), &astro, &pool_asset_infos, &routes).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidSwapRoute { .. }));
+
+        // Without a given route, a direct pair with any pool asset is used
+        let route = find_route(deps.as_ref(), &astro, &pool_asset_infos, &[]).unwrap();
+        assert_eq!(route, (vec![astro_swap(astro_astro, "uluna")], None));
+
+        // Without a given route or a direct pair there is no route
+        let err = find_route(
+            deps.as_ref(),
+            &AssetInfo::Native("uatom".to_string()),
+            &pool_asset_infos,
+            &[],
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::NoSwapRoute { offer_asset } if offer_asset.contains("uatom")
+        ));
+    }
+}
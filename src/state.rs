@@ -1,4 +1,26 @@
+use cosmwasm_schema::cw_serde;
 use cosmwasm_std::Addr;
-use cw_storage_plus::Item;
+use cw_storage_plus::{Item, Map};
 
-pub const ASTROPORT_FACTORY: Item<Addr> = Item::new("astroport_factory");
+#[cw_serde]
+pub struct Config {
+    pub astroport_factory: Addr,
+    /// Address that can pause the contract and individual pools, in addition
+    /// to the owner
+    pub guardian: Option<Addr>,
+    /// The Astroport generator, in which LP tokens are staked when
+    /// `auto_stake` is set
+    pub astroport_generator: Option<Addr>,
+    /// The Astroport router, used to swap assets that are not in the pool
+    pub astroport_router: Option<Addr>,
+}
+
+pub const CONFIG: Item<Config> = Item::new("config");
+
+pub const OWNER: Item<Addr> = Item::new("owner");
+pub const PROPOSED_OWNER: Item<Addr> = Item::new("proposed_owner");
+
+/// Whether all zap messages are paused
+pub const PAUSED: Item<bool> = Item::new("paused");
+/// Pair addresses of the pools for which zap messages are paused
+pub const PAUSED_POOLS: Map<&Addr, bool> = Map::new("paused_pools");
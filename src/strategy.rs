@@ -0,0 +1,105 @@
+//! Module containing the strategies used to balance assets for the different
+//! kinds of pools
+
+use cosmwasm_std::{Decimal, Deps, Uint128};
+use cw_asset::Asset;
+use cw_dex::traits::Pool;
+
+use crate::error::ContractError;
+use crate::math::calc_xyk_spot_return;
+
+pub mod astroport;
+
+/// The calculations needed to balance assets for a kind of pool. All arrays
+/// of assets passed to the methods must be in the same order.
+pub trait BalancingStrategy {
+    /// Name of the strategy, used in events and errors
+    fn name(&self) -> &str;
+
+    /// Returns whether the pool accepts liquidity in any ratio, in which case
+    /// no swap is needed to provide liquidity and all provided assets are
+    /// used.
+    fn accepts_any_ratio(&self) -> bool;
+
+    /// Calculates the swap of one of `assets` into the other that gives the
+    /// most LP tokens when providing to a pool with the given `reserves`.
+    ///
+    /// Returns `(offer_asset, return_asset): (Asset,Asset)`
+    fn calc_balancing_swap(
+        &self,
+        assets: [Asset; 2],
+        reserves: [Asset; 2],
+    ) -> Result<(Asset, Asset), ContractError>;
+
+    /// Calculates the amount of LP tokens minted when providing `amounts` to a
+    /// pool with the given `reserves` and `total_share` of LP tokens
+    fn calc_lp_out(
+        &self,
+        amounts: [Asset; 2],
+        reserves: [Asset; 2],
+        total_share: Uint128,
+    ) -> Result<Uint128, ContractError>;
+
+    /// Calculates how much `offer_asset` is worth in the other asset at the
+    /// spot price of a pool with the given `reserves`, before fees and price
+    /// impact. The `max_spread` of the balancing swap is measured against it.
+    /// Defaults to the ratio of the reserves, as in constant product pools.
+    fn calc_spot_return(
+        &self,
+        offer_asset: &Asset,
+        reserves: [Asset; 2],
+    ) -> Result<Uint128, ContractError> {
+        let offer = offer_index(offer_asset, &reserves);
+        Ok(calc_xyk_spot_return(
+            offer_asset.amount,
+            reserves[offer].amount,
+            reserves[1 - offer].amount,
+        )?)
+    }
+
+    /// Returns the amount of the ask asset sent out of the pool to the maker
+    /// by a swap returning `return_asset`, on top of the returned amount
+    fn maker_fee_amount(&self, _return_asset: &Asset) -> Uint128 {
+        Uint128::zero()
+    }
+
+    /// Calculates the swap of one of the `withdrawn` assets into the other
+    /// that splits their value according to `weights`, at the price of a pool
+    /// with the given `reserves` after the swap.
+    ///
+    /// Returns `(offer_asset, return_asset): (Asset,Asset)`
+    fn calc_weighted_swap(
+        &self,
+        _withdrawn: [Asset; 2],
+        _weights: [Decimal; 2],
+        _reserves: [Asset; 2],
+    ) -> Result<(Asset, Asset), ContractError> {
+        Err(ContractError::UnsupportedPairType {
+            pair_type: self.name().to_string(),
+        })
+    }
+}
+
+/// Returns the index of `offer_asset` in `reserves`
+fn offer_index(offer_asset: &Asset, reserves: &[Asset; 2]) -> usize {
+    if reserves[0].info == offer_asset.info {
+        0
+    } else {
+        1
+    }
+}
+
+/// A pool that can choose the strategy used to balance assets for it
+pub trait BalancingPool: Pool {
+    /// Returns the balancing strategy for the kind of this pool. Any
+    /// parameters the strategy needs are queried once here, so the strategy
+    /// should only be used within a single execution or query.
+    fn balancing_strategy<'a>(
+        &'a self,
+        deps: Deps<'a>,
+    ) -> Result<Box<dyn BalancingStrategy + 'a>, ContractError>;
+
+    /// Queries the reserves of the pool, in the order of the pool assets,
+    /// together with the total amount of LP tokens issued
+    fn query_reserves(&self, deps: Deps) -> Result<(Vec<Asset>, Uint128), ContractError>;
+}
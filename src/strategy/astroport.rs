@@ -0,0 +1,514 @@
+//! Module containing the balancing strategies for the Astroport pair types
+
+use cosmwasm_std::{Decimal, Deps, Uint128};
+use cw_asset::{Asset, AssetInfo};
+use cw_dex::astroport::helpers::query_fee_info;
+use cw_dex::astroport::msg::PairType;
+use cw_dex::astroport::AstroportPool;
+
+use super::{offer_index, BalancingPool, BalancingStrategy};
+use crate::error::ContractError;
+use crate::math::pcl::{
+    calc_pcl_balancing_swap, calc_pcl_lp_out, calc_pcl_spot_return, ConcentratedPoolParams,
+};
+use crate::math::{
+    calc_simulated_balancing_swap, calc_stable_balancing_swap, calc_stable_lp_out,
+    calc_stable_spot_return, calc_xyk_balancing_swap, calc_xyk_lp_out, calc_xyk_weighted_swap,
+};
+use crate::pool::{
+    is_concentrated, query_concentrated_pool_params, query_simulation, query_stable_pool_params,
+};
+use crate::state::CONFIG;
+
+impl BalancingPool for AstroportPool {
+    fn balancing_strategy<'a>(
+        &'a self,
+        deps: Deps<'a>,
+    ) -> Result<Box<dyn BalancingStrategy + 'a>, ContractError> {
+        Ok(match self.pair_type {
+            PairType::Xyk {} => {
+                let (fee, maker_fee) = query_fee_rates(deps, self)?;
+                Box::new(XykStrategy { fee, maker_fee })
+            }
+            PairType::Stable {} => {
+                let asset_infos = query_asset_infos(deps, self)?;
+                let (amp, precisions) =
+                    query_stable_pool_params(&deps.querier, self, &asset_infos)?;
+                let (fee, maker_fee) = query_fee_rates(deps, self)?;
+                Box::new(StableStrategy {
+                    amp,
+                    fee,
+                    maker_fee,
+                    precisions: asset_infos.into_iter().zip(precisions).collect(),
+                })
+            }
+            PairType::Custom(_) if is_concentrated(&self.pair_type) => {
+                let asset_infos = query_asset_infos(deps, self)?;
+                let (params, precisions) =
+                    query_concentrated_pool_params(&deps.querier, self, &asset_infos)?;
+                Box::new(ConcentratedStrategy {
+                    params,
+                    precisions: asset_infos.into_iter().zip(precisions).collect(),
+                })
+            }
+            PairType::Custom(_) => Box::new(SimulatedStrategy { deps, pool: self }),
+        })
+    }
+
+    fn query_reserves(&self, deps: Deps) -> Result<(Vec<Asset>, Uint128), ContractError> {
+        let pool_res = self.query_pool_info(&deps.querier)?;
+        Ok((
+            pool_res.assets.iter().map(Asset::from).collect(),
+            pool_res.total_share,
+        ))
+    }
+}
+
+/// Queries the total fee rate of XYK and stable pools from the Astroport
+/// factory, together with the share of the commission that is sent out of the
+/// pool to the maker. The maker fee is zero if the factory has no fee address,
+/// since the pair then keeps the whole commission.
+fn query_fee_rates(deps: Deps, pool: &AstroportPool) -> Result<(Decimal, Decimal), ContractError> {
+    let fee_info = query_fee_info(
+        &deps.querier,
+        CONFIG.load(deps.storage)?.astroport_factory,
+        pool.pair_type.clone(),
+    )?;
+    let maker_fee_rate = match fee_info.fee_address {
+        Some(_) => fee_info.maker_fee_rate,
+        None => Decimal::zero(),
+    };
+    Ok((fee_info.total_fee_rate, maker_fee_rate))
+}
+
+/// Queries the infos of the assets in `pool`
+fn query_asset_infos(deps: Deps, pool: &AstroportPool) -> Result<Vec<AssetInfo>, ContractError> {
+    Ok(pool
+        .query_pool_info(&deps.querier)?
+        .assets
+        .iter()
+        .map(|asset| Asset::from(asset).info)
+        .collect())
+}
+
+/// Returns the precisions of the assets in `reserves`, in the same order
+fn find_precisions(
+    precisions: &[(AssetInfo, u8)],
+    reserves: &[Asset; 2],
+) -> Result<[u8; 2], ContractError> {
+    let find = |reserve: &Asset| {
+        precisions
+            .iter()
+            .find(|(info, _)| info == &reserve.info)
+            .map(|(_, precision)| *precision)
+            .ok_or_else(|| ContractError::AssetNotInPool {
+                asset: reserve.info.to_string(),
+            })
+    };
+    Ok([find(&reserves[0])?, find(&reserves[1])?])
+}
+
+/// Returns the amount sent to the maker by a pair charging `fee` on a swap
+/// returning `return_asset`, of which `maker_fee` is the maker's share
+fn calc_maker_fee_amount(return_asset: &Asset, fee: Decimal, maker_fee: Decimal) -> Uint128 {
+    // A swap only returns assets if the fee is below one
+    if return_asset.amount.is_zero() {
+        return Uint128::zero();
+    }
+    // The commission is taken from the amount before fees, of which the
+    // returned amount is the rest
+    let commission_amount = return_asset
+        .amount
+        .multiply_ratio(fee.atomics(), (Decimal::one() - fee).atomics());
+    commission_amount * maker_fee
+}
+
+/// Returns the amounts of `assets`
+fn to_amounts(assets: &[Asset; 2]) -> [Uint128; 2] {
+    [assets[0].amount, assets[1].amount]
+}
+
+/// Strategy for Astroport constant product pairs, which need a swap into the
+/// pool ratio before providing
+pub struct XykStrategy {
+    /// Total fee rate of the pair
+    pub fee: Decimal,
+    /// Share of the commission sent out of the pair to the maker
+    pub maker_fee: Decimal,
+}
+
+impl BalancingStrategy for XykStrategy {
+    fn name(&self) -> &str {
+        "xyk"
+    }
+
+    fn accepts_any_ratio(&self) -> bool {
+        false
+    }
+
+    fn calc_balancing_swap(
+        &self,
+        assets: [Asset; 2],
+        reserves: [Asset; 2],
+    ) -> Result<(Asset, Asset), ContractError> {
+        Ok(calc_xyk_balancing_swap(
+            assets,
+            reserves[0].amount,
+            reserves[1].amount,
+            self.fee,
+            self.maker_fee,
+        )?)
+    }
+
+    fn calc_lp_out(
+        &self,
+        amounts: [Asset; 2],
+        reserves: [Asset; 2],
+        total_share: Uint128,
+    ) -> Result<Uint128, ContractError> {
+        Ok(calc_xyk_lp_out(
+            to_amounts(&amounts),
+            to_amounts(&reserves),
+            total_share,
+        )?)
+    }
+
+    fn maker_fee_amount(&self, return_asset: &Asset) -> Uint128 {
+        calc_maker_fee_amount(return_asset, self.fee, self.maker_fee)
+    }
+
+    fn calc_weighted_swap(
+        &self,
+        withdrawn: [Asset; 2],
+        weights: [Decimal; 2],
+        reserves: [Asset; 2],
+    ) -> Result<(Asset, Asset), ContractError> {
+        Ok(calc_xyk_weighted_swap(
+            withdrawn,
+            weights,
+            reserves[0].amount,
+            reserves[1].amount,
+            self.fee,
+        )?)
+    }
+}
+
+/// Strategy for Astroport StableSwap pairs, which accept liquidity in any
+/// ratio but charge an imbalance fee, so we only swap as much as gives us more
+/// LP tokens
+pub struct StableStrategy {
+    /// Amplification of the pair
+    pub amp: Decimal,
+    /// Total fee rate of the pair
+    pub fee: Decimal,
+    /// Share of the commission sent out of the pair to the maker
+    pub maker_fee: Decimal,
+    /// Precisions of the assets in the pair
+    pub precisions: Vec<(AssetInfo, u8)>,
+}
+
+impl BalancingStrategy for StableStrategy {
+    fn name(&self) -> &str {
+        "stable"
+    }
+
+    fn accepts_any_ratio(&self) -> bool {
+        true
+    }
+
+    fn calc_balancing_swap(
+        &self,
+        assets: [Asset; 2],
+        reserves: [Asset; 2],
+    ) -> Result<(Asset, Asset), ContractError> {
+        Ok(calc_stable_balancing_swap(
+            assets,
+            to_amounts(&reserves),
+            find_precisions(&self.precisions, &reserves)?,
+            self.amp,
+            self.fee,
+        )?)
+    }
+
+    fn calc_lp_out(
+        &self,
+        amounts: [Asset; 2],
+        reserves: [Asset; 2],
+        total_share: Uint128,
+    ) -> Result<Uint128, ContractError> {
+        Ok(calc_stable_lp_out(
+            to_amounts(&amounts),
+            to_amounts(&reserves),
+            find_precisions(&self.precisions, &reserves)?,
+            self.amp,
+            self.fee,
+            total_share,
+        )?)
+    }
+
+    fn calc_spot_return(
+        &self,
+        offer_asset: &Asset,
+        reserves: [Asset; 2],
+    ) -> Result<Uint128, ContractError> {
+        let offer = offer_index(offer_asset, &reserves);
+        let precisions = find_precisions(&self.precisions, &reserves)?;
+        Ok(calc_stable_spot_return(
+            offer_asset.amount,
+            [precisions[offer], precisions[1 - offer]],
+        )?)
+    }
+
+    fn maker_fee_amount(&self, return_asset: &Asset) -> Uint128 {
+        calc_maker_fee_amount(return_asset, self.fee, self.maker_fee)
+    }
+}
+
+/// Strategy for Astroport concentrated liquidity pairs, which also accept
+/// liquidity in any ratio, but charge a fee on deposits that are not balanced
+/// at their price scale
+pub struct ConcentratedStrategy {
+    /// Parameters of the pair, including its price scale
+    pub params: ConcentratedPoolParams,
+    /// Precisions of the assets in the pair
+    pub precisions: Vec<(AssetInfo, u8)>,
+}
+
+impl BalancingStrategy for ConcentratedStrategy {
+    fn name(&self) -> &str {
+        "concentrated"
+    }
+
+    fn accepts_any_ratio(&self) -> bool {
+        true
+    }
+
+    fn calc_balancing_swap(
+        &self,
+        assets: [Asset; 2],
+        reserves: [Asset; 2],
+    ) -> Result<(Asset, Asset), ContractError> {
+        Ok(calc_pcl_balancing_swap(
+            assets,
+            to_amounts(&reserves),
+            find_precisions(&self.precisions, &reserves)?,
+            &self.params,
+        )?)
+    }
+
+    fn calc_lp_out(
+        &self,
+        amounts: [Asset; 2],
+        reserves: [Asset; 2],
+        total_share: Uint128,
+    ) -> Result<Uint128, ContractError> {
+        Ok(calc_pcl_lp_out(
+            to_amounts(&amounts),
+            to_amounts(&reserves),
+            find_precisions(&self.precisions, &reserves)?,
+            &self.params,
+            total_share,
+        )?)
+    }
+
+    fn calc_spot_return(
+        &self,
+        offer_asset: &Asset,
+        reserves: [Asset; 2],
+    ) -> Result<Uint128, ContractError> {
+        Ok(calc_pcl_spot_return(
+            offer_asset.amount,
+            offer_index(offer_asset, &reserves),
+            find_precisions(&self.precisions, &reserves)?,
+            &self.params,
+        )?)
+    }
+}
+
+/// Strategy for other custom Astroport pairs, for which we have no formula.
+/// The pair simulates swaps while searching for the swap that matches the
+/// pool ratio, and after it the pair is assumed to mint LP tokens in
+/// proportion to the assets provided, like XYK pools.
+pub struct SimulatedStrategy<'a> {
+    deps: Deps<'a>,
+    pool: &'a AstroportPool,
+}
+
+impl BalancingStrategy for SimulatedStrategy<'_> {
+    fn name(&self) -> &str {
+        "custom"
+    }
+
+    fn accepts_any_ratio(&self) -> bool {
+        false
+    }
+
+    fn calc_balancing_swap(
+        &self,
+        assets: [Asset; 2],
+        reserves: [Asset; 2],
+    ) -> Result<(Asset, Asset), ContractError> {
+        Ok(calc_simulated_balancing_swap(
+            assets,
+            to_amounts(&reserves),
+            |offer_asset| query_simulation(&self.deps.querier, self.pool, offer_asset),
+        )?)
+    }
+
+    fn calc_lp_out(
+        &self,
+        amounts: [Asset; 2],
+        reserves: [Asset; 2],
+        total_share: Uint128,
+    ) -> Result<Uint128, ContractError> {
+        Ok(calc_xyk_lp_out(
+            to_amounts(&amounts),
+            to_amounts(&reserves),
+            total_share,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cosmwasm_std::testing::mock_dependencies;
+    use cosmwasm_std::{
+        Addr, Binary, ContractResult, Decimal, SystemError, SystemResult, Uint128, WasmQuery,
+    };
+    use cw_asset::Asset;
+    use cw_dex::astroport::msg::PairType;
+    use cw_dex::astroport::AstroportPool;
+
+    use crate::math::calc_xyk_balancing_swap;
+    use crate::state::{Config, CONFIG};
+    use crate::strategy::BalancingPool;
+
+    /// Returns the balancing swap of the XYK strategy, and the maker fee sent
+    /// by it, when the factory returns `fee_info`
+    fn xyk_strategy_swap(
+        fee_info: &'static [u8],
+        assets: [Asset; 2],
+        reserves: [Asset; 2],
+    ) -> ((Asset, Asset), Uint128) {
+        let mut deps = mock_dependencies();
+        CONFIG
+            .save(
+                deps.as_mut().storage,
+                &Config {
+                    astroport_factory: Addr::unchecked("factory"),
+                    guardian: None,
+                    astroport_generator: None,
+                    astroport_router: None,
+                },
+            )
+            .unwrap();
+        deps.querier.update_wasm(move |query| match query {
+            WasmQuery::Smart { contract_addr, .. } if contract_addr == "factory" => {
+                SystemResult::Ok(ContractResult::Ok(Binary::from(fee_info)))
+            }
+            _ => SystemResult::Err(SystemError::NoSuchContract {
+                addr: "other".to_string(),
+            }),
+        });
+        let pool = AstroportPool {
+            pair_addr: Addr::unchecked("pair"),
+            lp_token_addr: Addr::unchecked("lp_token"),
+            pair_type: PairType::Xyk {},
+        };
+
+        let strategy = pool.balancing_strategy(deps.as_ref()).unwrap();
+        let swap = strategy.calc_balancing_swap(assets, reserves).unwrap();
+        let maker_fee_amount = strategy.maker_fee_amount(&swap.1);
+        (swap, maker_fee_amount)
+    }
+
+    #[test]
+    fn test_xyk_strategy_maker_fee() {
+        let assets = [
+            Asset::native("uatom", 0u128),
+            Asset::native("uosmo", 2_000_000_000u128),
+        ];
+        let reserves = [
+            Asset::native("uatom", 1_000_000_000_000u128),
+            Asset::native("uosmo", 1_000_000_000_000u128),
+        ];
+        let fee = Decimal::permille(3);
+
+        // Without a fee address the pair keeps the whole commission
+        let (swap, maker_fee_amount) = xyk_strategy_swap(
+            br#"{"fee_address":null,"total_fee_bps":30,"maker_fee_bps":3333}"#,
+            assets.clone(),
+            reserves.clone(),
+        );
+        let expected = calc_xyk_balancing_swap(
+            assets.clone(),
+            reserves[0].amount,
+            reserves[1].amount,
+            fee,
+            Decimal::zero(),
+        )
+        .unwrap();
+        assert_eq!(swap, expected);
+        assert_eq!(maker_fee_amount, Uint128::zero());
+
+        // With one, the maker share of the commission leaves the pair
+        let (swap, maker_fee_amount) = xyk_strategy_swap(
+            br#"{"fee_address":"maker","total_fee_bps":30,"maker_fee_bps":3333}"#,
+            assets.clone(),
+            reserves.clone(),
+        );
+        let expected = calc_xyk_balancing_swap(
+            assets,
+            reserves[0].amount,
+            reserves[1].amount,
+            fee,
+            Decimal::from_ratio(3333u128, 10_000u128),
+        )
+        .unwrap();
+        assert_eq!(swap, expected);
+        assert!(maker_fee_amount > Uint128::zero());
+    }
+
+    #[test]
+    fn test_query_reserves() {
+        let mut deps = mock_dependencies();
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { contract_addr, .. } if contract_addr == "pair" => {
+                SystemResult::Ok(ContractResult::Ok(Binary::from(
+                    br#"{
+                        "assets": [
+                            {
+                                "info": { "native_token": { "denom": "uatom" } },
+                                "amount": "1000"
+                            },
+                            {
+                                "info": { "token": { "contract_addr": "token" } },
+                                "amount": "2000"
+                            }
+                        ],
+                        "total_share": "1414"
+                    }"#
+                    .as_ref(),
+                )))
+            }
+            _ => SystemResult::Err(SystemError::NoSuchContract {
+                addr: "other".to_string(),
+            }),
+        });
+        let pool = AstroportPool {
+            pair_addr: Addr::unchecked("pair"),
+            lp_token_addr: Addr::unchecked("lp_token"),
+            pair_type: PairType::Xyk {},
+        };
+
+        let (reserves, total_share) = pool.query_reserves(deps.as_ref()).unwrap();
+        assert_eq!(
+            reserves,
+            vec![
+                Asset::native("uatom", 1_000u128),
+                Asset::cw20(Addr::unchecked("token"), 2_000u128),
+            ]
+        );
+        assert_eq!(total_share, Uint128::from(1_414u128));
+    }
+}
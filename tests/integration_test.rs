@@ -3,16 +3,22 @@ use astroport::factory::{FeeInfoResponse, PairType, QueryMsg as FactoryQueryMsg}
 
 use astroport::pair::ExecuteMsg as PairExecuteMsg;
 use astroport::pair::QueryMsg as PairQueryMsg;
-use astroport_liquidity_helper::math::calc_xyk_balancing_swap;
+use astroport_liquidity_helper::math::{
+    calc_stable_balancing_swap, calc_stable_lp_out, calc_xyk_balancing_swap,
+};
+use astroport_liquidity_helper::pool::PairInfo;
 use astroport_liquidity_helper::{helpers::LiquidityHelper, msg::InstantiateMsg};
-use cosmwasm_std::{to_binary, Addr, Coin, Decimal, Uint128};
-use cw20::{AllowanceResponse, Cw20ExecuteMsg, Cw20QueryMsg};
+use cosmwasm_std::{to_binary, Addr, Binary, Coin, Decimal, Uint128};
+use cw20::{
+    AllowanceResponse, BalanceResponse, Cw20Coin, Cw20ExecuteMsg, Cw20QueryMsg, MinterResponse,
+    TokenInfoResponse,
+};
 use cw_asset::{Asset, AssetInfo, AssetList};
 use cw_dex::astroport::msg::{PoolResponse, SimulationResponse};
 use cw_dex::astroport::AstroportPool;
 use cw_it::astroport::{create_astroport_pair, instantiate_astroport, upload_astroport_contracts};
-use cw_it::Cli;
 use cw_it::config::TestConfig;
+use cw_it::Cli;
 use cw_it::{app::App as RpcRunner, astroport::AstroportContracts};
 
 use osmosis_testing::OsmosisTestApp;
@@ -20,12 +26,48 @@ use osmosis_testing::{
     cosmrs::proto::cosmwasm::wasm::v1::MsgExecuteContractResponse, Account, Module, Runner,
     SigningAccount, Wasm,
 };
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::str::FromStr;
 
 const TEST_CONFIG_PATH: &str = "tests/configs/terra.yaml";
 pub const ASTROPORT_LIQUIDITY_HELPER_WASM_FILE: &str = "artifacts/astroport_liquidity_helper.wasm";
 
+/// Instantiate message of the Astroport cw20 token contract
+#[derive(Serialize)]
+struct TokenInstantiateMsg {
+    name: String,
+    symbol: String,
+    decimals: u8,
+    initial_balances: Vec<Cw20Coin>,
+    mint: Option<MinterResponse>,
+}
+
+/// CreatePair message of the Astroport factory for any number of assets. The
+/// message of the pinned Astroport version only takes two assets.
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum FactoryMultiAssetExecuteMsg {
+    CreatePair {
+        pair_type: PairType,
+        asset_infos: Vec<AstroAssetInfo>,
+        init_params: Option<Binary>,
+    },
+}
+
+/// Pair query of the Astroport factory for any number of assets
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum FactoryMultiAssetQueryMsg {
+    Pair { asset_infos: Vec<AstroAssetInfo> },
+}
+
+/// Pool query response of an Astroport pair with any number of assets
+#[derive(Deserialize)]
+struct MultiAssetPoolResponse {
+    assets: Vec<AstroAsset>,
+}
+
 #[test]
 /// Runs all tests against the Osmosis bindings.
 /// This works since there are no big differences between the chains.
@@ -41,6 +83,8 @@ pub fn test_with_osmosis_bindings() {
 
     test_balancing_provide_liquidity(&app, &accs, &astroport_code_ids);
     test_calc_xyk_balancing_swap(&app, &accs, &astroport_code_ids);
+    test_calc_stable_balancing_swap(&app, &accs, &astroport_code_ids);
+    test_balancing_provide_liquidity_multi_asset_stable(&app, &accs, &astroport_code_ids);
 }
 
 #[test]
@@ -63,6 +107,8 @@ pub fn test_with_localterra() {
     // Test basic liquidity helper functionality
     test_balancing_provide_liquidity(&app, &accs, &astroport_code_ids);
     test_calc_xyk_balancing_swap(&app, &accs, &astroport_code_ids);
+    test_calc_stable_balancing_swap(&app, &accs, &astroport_code_ids);
+    test_balancing_provide_liquidity_multi_asset_stable(&app, &accs, &astroport_code_ids);
 }
 
 /// Instantiates the liquidity helper contract
@@ -95,7 +141,11 @@ where
         .instantiate(
             astroport_liquidity_helper_code_id,
             &InstantiateMsg {
+                owner: admin.address(),
                 astroport_factory: astroport_contracts.factory.address.clone(),
+                guardian: None,
+                astroport_generator: None,
+                astroport_router: None,
             },
             Some(&admin.address()), // contract admin used for migration
             Some("Astroport Liquidity Helper"), // contract label
@@ -106,8 +156,6 @@ where
         .data
         .address;
 
-    
-
     LiquidityHelper::new(Addr::unchecked(astroport_liquidity_helper))
 }
 
@@ -194,6 +242,7 @@ pub fn test_calc_xyk_balancing_swap<'a, R>(
         )
         .unwrap();
     let total_fee_rate = Decimal::from_ratio(res.total_fee_bps, 10000u16);
+    let maker_fee_rate = Decimal::from_ratio(res.maker_fee_bps, 10000u16);
 
     // Calculate balancing swap
     let assets = [
@@ -210,7 +259,8 @@ pub fn test_calc_xyk_balancing_swap<'a, R>(
     let reserve2 = Uint128::from(3_000_000_000_000u128);
 
     let (offer_asset, return_asset) =
-        calc_xyk_balancing_swap(assets, reserve1, reserve2, total_fee_rate).unwrap();
+        calc_xyk_balancing_swap(assets, reserve1, reserve2, total_fee_rate, maker_fee_rate)
+            .unwrap();
 
     // Simulate swap
     let simulation_result: SimulationResponse = wasm
@@ -229,6 +279,205 @@ pub fn test_calc_xyk_balancing_swap<'a, R>(
     assert_eq!(simulation_result.return_amount, return_asset.amount);
 }
 
+pub fn test_calc_stable_balancing_swap<'a, R>(
+    app: &'a R,
+    accs: &[SigningAccount],
+    astroport_code_ids: &HashMap<String, u64>,
+) where
+    R: Runner<'a>,
+{
+    let wasm = Wasm::new(app);
+    let admin = &accs[0];
+
+    // Instantiate Astroport contracts
+    let astroport_contracts = instantiate_astroport(app, admin, astroport_code_ids);
+
+    let astro_token = astroport_contracts.astro_token.address.clone();
+
+    // Create StableSwap pool with an amplification of 10
+    let asset_infos: [AstroAssetInfo; 2] = [
+        AstroAssetInfo::NativeToken {
+            denom: "uluna".into(),
+        },
+        AstroAssetInfo::Token {
+            contract_addr: Addr::unchecked(&astro_token),
+        },
+    ];
+    let (uluna_astro_pair_addr, uluna_astro_lp_token) = create_astroport_pair(
+        app,
+        &astroport_contracts.factory.address,
+        PairType::Stable {},
+        asset_infos.clone(),
+        Some(Binary::from(br#"{"amp":10}"#.as_ref())),
+        admin,
+    );
+    let amp = Decimal::from_ratio(10u128, 1u128);
+
+    // Increase allowance of astro token for Pair contract
+    let increase_allowance_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: uluna_astro_pair_addr.clone(),
+        amount: Uint128::from(4_000_000_000_000u128),
+        expires: None,
+    };
+    let _res = wasm
+        .execute(&astro_token, &increase_allowance_msg, &[], admin)
+        .unwrap();
+
+    // Provides liquidity to the pair, returning the LP tokens minted
+    let provide_liquidity = |amounts: [Uint128; 2]| -> Uint128 {
+        let lp_balance = || -> Uint128 {
+            let res: BalanceResponse = wasm
+                .query(
+                    &uluna_astro_lp_token,
+                    &Cw20QueryMsg::Balance {
+                        address: admin.address(),
+                    },
+                )
+                .unwrap();
+            res.balance
+        };
+        let lp_balance_before = lp_balance();
+
+        let provide_liq_msg = PairExecuteMsg::ProvideLiquidity {
+            assets: [
+                AstroAsset {
+                    amount: amounts[0],
+                    info: asset_infos[0].clone(),
+                },
+                AstroAsset {
+                    amount: amounts[1],
+                    info: asset_infos[1].clone(),
+                },
+            ],
+            slippage_tolerance: None,
+            auto_stake: Some(false),
+            receiver: None,
+        };
+        wasm.execute(
+            &uluna_astro_pair_addr,
+            &provide_liq_msg,
+            &[Coin {
+                amount: amounts[0],
+                denom: "uluna".into(),
+            }],
+            admin,
+        )
+        .unwrap();
+
+        lp_balance() - lp_balance_before
+    };
+
+    // Provide liquidity to get an imbalanced pool
+    let reserves = [
+        Uint128::from(1_000_000_000_000u128),
+        Uint128::from(3_000_000_000_000u128),
+    ];
+    provide_liquidity(reserves);
+    let pool_res: PoolResponse = wasm
+        .query(&uluna_astro_pair_addr, &PairQueryMsg::Pool {})
+        .unwrap();
+    let total_share = pool_res.total_share;
+
+    // Query fee info
+    let res: FeeInfoResponse = wasm
+        .query(
+            &astroport_contracts.factory.address,
+            &FactoryQueryMsg::FeeInfo {
+                pair_type: PairType::Stable {},
+            },
+        )
+        .unwrap();
+    let total_fee_rate = Decimal::from_ratio(res.total_fee_bps, 10000u16);
+
+    // Native tokens have 6 decimals in the pair
+    let token_info: TokenInfoResponse = wasm
+        .query(&astro_token, &Cw20QueryMsg::TokenInfo {})
+        .unwrap();
+    let precisions = [6u8, token_info.decimals];
+
+    // Calculate balancing swap for a single sided deposit of uluna
+    let assets = [
+        Asset {
+            amount: Uint128::from(1_000_000_000_000u128),
+            info: AssetInfo::native("uluna".to_string()),
+        },
+        Asset {
+            amount: Uint128::zero(),
+            info: AssetInfo::Cw20(Addr::unchecked(&astro_token)),
+        },
+    ];
+    let (offer_asset, return_asset) =
+        calc_stable_balancing_swap(assets.clone(), reserves, precisions, amp, total_fee_rate)
+            .unwrap();
+    assert_eq!(offer_asset.info, assets[0].info);
+    assert!(!offer_asset.amount.is_zero());
+
+    // Simulate swap
+    let simulate = |offer_amount: Uint128| -> Uint128 {
+        let simulation_result: SimulationResponse = wasm
+            .query(
+                &uluna_astro_pair_addr,
+                &PairQueryMsg::Simulation {
+                    offer_asset: AstroAsset {
+                        amount: offer_amount,
+                        info: asset_infos[0].clone(),
+                    },
+                },
+            )
+            .unwrap();
+        simulation_result.return_amount
+    };
+
+    // Check if the simulation result is correct
+    assert_eq!(simulate(offer_asset.amount), return_asset.amount);
+
+    // LP tokens received when swapping `offer_amount` with the pair and
+    // providing the rest. The maker fee leaving the pool is left out, since it
+    // is tiny compared to the amounts swapped.
+    let lp_out = |offer_amount: Uint128| -> Uint128 {
+        let return_amount = simulate(offer_amount);
+        calc_stable_lp_out(
+            [assets[0].amount - offer_amount, return_amount],
+            [reserves[0] + offer_amount, reserves[1] - return_amount],
+            precisions,
+            amp,
+            total_fee_rate,
+            total_share,
+        )
+        .unwrap()
+    };
+
+    // Swapping slightly more or less does not give more LP tokens
+    let best_lp_out = lp_out(offer_asset.amount);
+    for amount in [
+        offer_asset.amount * Decimal::percent(99),
+        std::cmp::min(offer_asset.amount * Decimal::percent(101), assets[0].amount),
+    ] {
+        assert!(best_lp_out >= lp_out(amount));
+    }
+
+    // Check that the LP tokens calculated, including the imbalance fee, are
+    // what the pair mints. The pair rounds in its own fixed point math, so
+    // allow one part in a million of difference.
+    let amounts = [assets[0].amount - offer_asset.amount, return_asset.amount];
+    let expected_lp_out = calc_stable_lp_out(
+        amounts,
+        reserves,
+        precisions,
+        amp,
+        total_fee_rate,
+        total_share,
+    )
+    .unwrap();
+    let minted = provide_liquidity(amounts);
+    let diff = if minted > expected_lp_out {
+        minted - expected_lp_out
+    } else {
+        expected_lp_out - minted
+    };
+    assert!(diff <= expected_lp_out / Uint128::from(1_000_000u128));
+}
+
 /// Tests the BalancingProvideLiquidity message
 pub fn test_balancing_provide_liquidity<R>(
     app: &R,
@@ -332,7 +581,17 @@ pub fn test_balancing_provide_liquidity<R>(
         ))
         .unwrap();
     let msgs = liquidity_helper
-        .balancing_provide_liquidity(assets, Uint128::one(), to_binary(&pool).unwrap(), None)
+        .balancing_provide_liquidity(
+            assets,
+            Uint128::one(),
+            to_binary(&pool).unwrap(),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
         .unwrap();
 
     let _res = app
@@ -363,3 +622,157 @@ pub fn test_balancing_provide_liquidity<R>(
     let pool_liquidity: AssetList = pool_liquidity.assets.to_vec().into();
     assert_eq!(&pool_liquidity, expected_liquidity_after_add);
 }
+
+/// Tests the BalancingProvideLiquidity message with a StableSwap pool of three
+/// assets, which the contract provides to through cw-dex
+pub fn test_balancing_provide_liquidity_multi_asset_stable<R>(
+    app: &R,
+    accs: &[SigningAccount],
+    astroport_code_ids: &HashMap<String, u64>,
+) where
+    R: for<'a> Runner<'a>,
+{
+    let admin = &accs[0];
+    let wasm = Wasm::new(app);
+
+    // Instantiate Astroport contracts
+    let astroport_contracts = instantiate_astroport(app, admin, astroport_code_ids);
+
+    let liquidity_helper =
+        setup_astroport_liquidity_provider_tests(app, accs, &astroport_contracts);
+    let astro_token = astroport_contracts.astro_token.address.clone();
+
+    // Instantiate another token for the third asset of the pool
+    let stable_token = wasm
+        .instantiate(
+            astroport_code_ids["astro_token"],
+            &TokenInstantiateMsg {
+                name: "Stable Token".to_string(),
+                symbol: "STBL".to_string(),
+                decimals: 6,
+                initial_balances: vec![Cw20Coin {
+                    address: admin.address(),
+                    amount: Uint128::from(1_000_000_000_000u128),
+                }],
+                mint: None,
+            },
+            None,
+            Some("Stable Token"),
+            &[],
+            admin,
+        )
+        .unwrap()
+        .data
+        .address;
+
+    // Create StableSwap pool of uluna and the two tokens
+    let asset_infos = vec![
+        AstroAssetInfo::NativeToken {
+            denom: "uluna".into(),
+        },
+        AstroAssetInfo::Token {
+            contract_addr: Addr::unchecked(&astro_token),
+        },
+        AstroAssetInfo::Token {
+            contract_addr: Addr::unchecked(&stable_token),
+        },
+    ];
+    wasm.execute(
+        &astroport_contracts.factory.address,
+        &FactoryMultiAssetExecuteMsg::CreatePair {
+            pair_type: PairType::Stable {},
+            asset_infos: asset_infos.clone(),
+            init_params: Some(Binary::from(br#"{"amp":10}"#.as_ref())),
+        },
+        &[],
+        admin,
+    )
+    .unwrap();
+    let pair_info: PairInfo = wasm
+        .query(
+            &astroport_contracts.factory.address,
+            &FactoryMultiAssetQueryMsg::Pair {
+                asset_infos: asset_infos.clone(),
+            },
+        )
+        .unwrap();
+    assert_eq!(pair_info.asset_infos, asset_infos);
+    let pool = AstroportPool {
+        lp_token_addr: pair_info.liquidity_token.clone(),
+        pair_addr: pair_info.contract_addr.clone(),
+        pair_type: cw_dex::astroport::msg::PairType::Stable {},
+    };
+
+    // Provides the given amounts of uluna and the two tokens through the
+    // liquidity helper
+    let balancing_provide_liquidity = |amounts: [u128; 3]| {
+        let mut assets = AssetList::new();
+        for (info, amount) in [
+            AssetInfo::native("uluna".to_string()),
+            AssetInfo::Cw20(Addr::unchecked(&astro_token)),
+            AssetInfo::Cw20(Addr::unchecked(&stable_token)),
+        ]
+        .into_iter()
+        .zip(amounts)
+        {
+            if amount > 0 {
+                assets.add(&Asset::new(info, amount)).unwrap();
+            }
+        }
+        let msgs = liquidity_helper
+            .balancing_provide_liquidity(
+                assets,
+                Uint128::one(),
+                to_binary(&pool).unwrap(),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        app.execute_cosmos_msgs::<MsgExecuteContractResponse>(&msgs, admin)
+            .unwrap();
+    };
+
+    // Returns the reserves of the pool and the LP token balance of the admin
+    let query_state = || -> (Vec<Uint128>, Uint128) {
+        let pool_res: MultiAssetPoolResponse = wasm
+            .query(pair_info.contract_addr.as_str(), &PairQueryMsg::Pool {})
+            .unwrap();
+        let lp_balance: BalanceResponse = wasm
+            .query(
+                pair_info.liquidity_token.as_str(),
+                &Cw20QueryMsg::Balance {
+                    address: admin.address(),
+                },
+            )
+            .unwrap();
+        (
+            pool_res.assets.iter().map(|asset| asset.amount).collect(),
+            lp_balance.balance,
+        )
+    };
+
+    // Provide the initial liquidity with all three assets
+    println!("Balancing provide liquidity to a pool of three assets");
+    balancing_provide_liquidity([1_000_000_000, 1_000_000_000, 1_000_000_000]);
+    let (reserves, lp_balance) = query_state();
+    assert_eq!(reserves, vec![Uint128::from(1_000_000_000u128); 3]);
+    assert!(!lp_balance.is_zero());
+
+    // Provide only two of the assets, so the third is provided with a zero
+    // amount
+    balancing_provide_liquidity([100_000, 300_000, 0]);
+    let (reserves_after, lp_balance_after) = query_state();
+    assert_eq!(
+        reserves_after,
+        vec![
+            Uint128::from(1_000_100_000u128),
+            Uint128::from(1_000_300_000u128),
+            Uint128::from(1_000_000_000u128),
+        ]
+    );
+    assert!(lp_balance_after > lp_balance);
+}